@@ -2,9 +2,11 @@ use std::collections::HashMap;
 
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 use sub_solver::{
+    alphabet::Alphabet,
     input::{clean_input, input_to_words},
+    key::Exclusions,
     load_wordlist, normalize,
-    solve::{Solution, Solver},
+    solve::{prune, Solution, Solver},
 };
 
 fn load_wordlist_bench(c: &mut Criterion) {
@@ -14,7 +16,7 @@ fn load_wordlist_bench(c: &mut Criterion) {
     ]
     .join("\n");
     c.bench_function("load_wordlist", |b| {
-        b.iter(|| load_wordlist(black_box(&wordlist)))
+        b.iter(|| load_wordlist(black_box(&wordlist), &Alphabet::default()))
     });
 }
 
@@ -25,14 +27,19 @@ fn normalize_bench(c: &mut Criterion) {
 
 fn clean_input_bench(c: &mut Criterion) {
     let word = "An   examplé sentence.";
-    c.bench_function("clean_input", |b| b.iter(|| clean_input(black_box(word))));
+    c.bench_function("clean_input", |b| {
+        b.iter(|| clean_input(black_box(word), &Alphabet::default()))
+    });
 }
 
 fn input_to_words_bench(c: &mut Criterion) {
     let input = "an example sentence";
-    let dictionary = load_wordlist(include_str!("../wordlist/english.txt"));
+    let dictionary = load_wordlist(
+        include_str!("../wordlist/english.txt"),
+        &Alphabet::default(),
+    );
     c.bench_function("input_to_words", |b| {
-        b.iter(|| input_to_words(black_box(input), black_box(&dictionary)))
+        b.iter(|| input_to_words(black_box(input), black_box(&dictionary), 0, 1))
     });
 }
 
@@ -43,18 +50,66 @@ fn solve_bench(c: &mut Criterion) {
         "all", "words", "should", "be", "good",
     ]
     .join("\n");
-    let dictionary = load_wordlist(&wordlist);
-    let cipher_words = input_to_words(ciphertext, &dictionary).unwrap();
+    let dictionary = load_wordlist(&wordlist, &Alphabet::default());
+    let cipher_words = input_to_words(ciphertext, &dictionary, 0, 1).unwrap();
 
     c.bench_function("solve", |b| {
         b.iter(|| Solver::new(&cipher_words).solve(HashMap::new(), None))
     });
 }
 
+fn solve_long_bench(c: &mut Criterion) {
+    // "some english text to showcase my tool in action as a longer example of what it can solve"
+    let ciphertext =
+        "tcxd dlzhrtm edbe ec tmcpfitd xs ecch rl ifercl it i hclzdu dbixkhd cq pmie re fil tchgd";
+    let dictionary = load_wordlist(
+        include_str!("../wordlist/english.txt"),
+        &Alphabet::default(),
+    );
+    let mut cipher_words = input_to_words(ciphertext, &dictionary, 0, 1).unwrap();
+    prune(
+        &mut cipher_words,
+        &Alphabet::default(),
+        1,
+        &Exclusions::default(),
+    );
+
+    let mut group = c.benchmark_group("large");
+    group.sample_size(10);
+    group.bench_function("solve_long", |b| {
+        b.iter(|| Solver::new(&cipher_words).solve(HashMap::new(), None))
+    });
+    group.finish();
+}
+
+fn prune_long_bench(c: &mut Criterion) {
+    let ciphertext =
+        "tcxd dlzhrtm edbe ec tmcpfitd xs ecch rl ifercl it i hclzdu dbixkhd cq pmie re fil tchgd";
+    let dictionary = load_wordlist(
+        include_str!("../wordlist/english.txt"),
+        &Alphabet::default(),
+    );
+    let cipher_words = input_to_words(ciphertext, &dictionary, 0, 1).unwrap();
+
+    let mut group = c.benchmark_group("large");
+    group.sample_size(10);
+    group.bench_function("prune_long", |b| {
+        b.iter(|| {
+            prune(
+                black_box(&mut cipher_words.clone()),
+                &Alphabet::default(),
+                1,
+                &Exclusions::default(),
+            )
+        })
+    });
+    group.finish();
+}
+
 fn apply_solution_bench(c: &mut Criterion) {
     let ciphertext = "x cbt tloap";
-    let solution = Solution {
-        key: [
+    let solution = Solution::new(
+        [
             ('x', 'a'),
             ('c', 'f'),
             ('b', 'e'),
@@ -67,7 +122,7 @@ fn apply_solution_bench(c: &mut Criterion) {
         .iter()
         .cloned()
         .collect(),
-    };
+    );
 
     c.bench_function("apply_solution", |b| {
         b.iter(|| solution.apply(black_box(ciphertext)))
@@ -81,6 +136,8 @@ criterion_group!(
     clean_input_bench,
     input_to_words_bench,
     solve_bench,
+    solve_long_bench,
+    prune_long_bench,
     apply_solution_bench
 );
 criterion_main!(benches);
@@ -0,0 +1,68 @@
+//! Turns the built-in wordlist into a dictionary at build time, so it doesn't have to be built or cached when running.
+//! This does the same as `load_wordlist` with the default alphabet, which a test in the crate checks
+
+use std::{collections::HashMap, env, fs, path::Path};
+
+const WORDLIST: &str = "wordlist/english.txt";
+
+/// Same as `sub_solver::normalize`
+fn normalize(s: &str) -> String {
+    let mut result = s.chars().collect::<Vec<char>>();
+    let mut replacement = b'A';
+
+    for i in 0..result.len() {
+        if result[i].is_ascii_uppercase() {
+            continue;
+        }
+        let c = result[i];
+        for r in result.iter_mut().filter(|r| **r == c) {
+            *r = replacement as char;
+        }
+        replacement += 1;
+    }
+
+    result.into_iter().collect()
+}
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed={WORDLIST}");
+
+    let contents = fs::read_to_string(WORDLIST).unwrap();
+    let mut dictionary: HashMap<String, HashMap<String, u64>> = HashMap::new();
+
+    for line in contents.lines() {
+        // Only ASCII is folded here, other letters need the alphabet of the crate
+        assert!(line.is_ascii(), "{WORDLIST} should only contain ASCII");
+        let line = line.trim();
+        let (word, count) = match line.rsplit_once(['\t', ' ']) {
+            Some((word, count)) if count.trim().parse::<u64>().is_ok() => {
+                (word, count.trim().parse().unwrap())
+            }
+            _ => (line, 1),
+        };
+
+        // Latin letters are lowercased, and anything else acts as a space
+        let word = word
+            .chars()
+            .map(|c| match c {
+                c if c.is_ascii_alphabetic() => c.to_ascii_lowercase(),
+                _ => ' ',
+            })
+            .collect::<String>()
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        if word.is_empty() {
+            continue;
+        }
+        *dictionary
+            .entry(normalize(&word))
+            .or_default()
+            .entry(word)
+            .or_default() += count;
+    }
+
+    let out = Path::new(&env::var("OUT_DIR").unwrap()).join("english.bin");
+    fs::write(out, bincode::serialize(&dictionary).unwrap()).unwrap();
+}
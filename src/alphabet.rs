@@ -0,0 +1,250 @@
+use std::{collections::BTreeSet, error::Error, fmt::Display, io::BufRead};
+
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+use unidecode::unidecode;
+
+use crate::{key::MAX_LETTERS, parse_wordlist_line};
+
+/// Built-in alphabets that can be chosen by name
+const PRESETS: &[(&str, &str)] = &[
+    ("latin", "abcdefghijklmnopqrstuvwxyz"),
+    ("dutch", "abcdefghijklmnopqrstuvwxyzáéíóúàèëïöü"),
+    ("greek", "αβγδεζηθικλμνξοπρστυφχψω"),
+    ("russian", "абвгдеёжзийклмнопрстуфхцчшщъыьэюя"),
+];
+
+/// Letters that are written differently depending on their position, but are the same letter
+const VARIANTS: &[(char, char)] = &[('ς', 'σ')];
+
+/// Letters that can be substituted, in the order they are shown in keys
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Alphabet {
+    letters: Vec<char>,
+    /// Letters that the ciphertext is written in, if they are not the letters of the alphabet (see `Symbols`)
+    symbols: Option<Vec<char>>,
+}
+impl Default for Alphabet {
+    fn default() -> Self {
+        Alphabet::preset("latin").unwrap()
+    }
+}
+impl Alphabet {
+    /// Create an alphabet from its letters in order.
+    /// Returns an error if a letter is not alphabetic, appears twice, or if there are too many letters
+    pub fn new(letters: &str) -> Result<Self, String> {
+        let mut result = Vec::new();
+
+        for c in letters.to_lowercase().chars() {
+            if !c.is_alphabetic() {
+                return Err(format!("Invalid alphabet letter: {c:?}"));
+            }
+            if result.contains(&c) {
+                return Err(format!("Duplicate alphabet letter: {c:?}"));
+            }
+            result.push(c);
+        }
+
+        if result.is_empty() {
+            return Err("Alphabet can't be empty".to_string());
+        }
+        if result.len() > MAX_LETTERS {
+            return Err(format!(
+                "Alphabet has {} letters, but at most {MAX_LETTERS} are supported",
+                result.len()
+            ));
+        }
+
+        Ok(Alphabet {
+            letters: result,
+            symbols: None,
+        })
+    }
+
+    /// One of the built-in alphabets: "latin", "dutch", "greek" or "russian"
+    pub fn preset(name: &str) -> Option<Self> {
+        PRESETS
+            .iter()
+            .find(|(preset, _)| *preset == name.to_lowercase())
+            .map(|(_, letters)| Alphabet {
+                letters: letters.chars().collect(),
+                symbols: None,
+            })
+    }
+
+    /// Use the name of a preset, or otherwise the letters of the alphabet themselves
+    ///
+    /// ```rust
+    /// use sub_solver::alphabet::Alphabet;
+    ///
+    /// assert_eq!(Alphabet::parse("greek").unwrap().len(), 24);
+    /// assert_eq!(Alphabet::parse("abc").unwrap().to_string(), "abc");
+    /// assert!(Alphabet::parse("abca").is_err());
+    /// ```
+    pub fn parse(alphabet: &str) -> Result<Self, String> {
+        match Alphabet::preset(alphabet) {
+            Some(alphabet) => Ok(alphabet),
+            None => Alphabet::new(alphabet),
+        }
+    }
+
+    /// All letters used in the words of a wordlist, in Unicode order
+    pub fn from_wordlist(contents: &str) -> Result<Self, String> {
+        Alphabet::read_wordlist(contents.as_bytes()).map_err(|e| e.to_string())
+    }
+
+    /// Same as `from_wordlist`, but reading one line at a time
+    pub fn read_wordlist(reader: impl BufRead) -> Result<Self, Box<dyn Error>> {
+        let mut letters = BTreeSet::new();
+        for line in reader.lines() {
+            letters.extend(
+                parse_wordlist_line(&line?)
+                    .0
+                    .to_lowercase()
+                    .chars()
+                    .filter(|c| c.is_alphabetic()),
+            );
+        }
+
+        Ok(Alphabet::new(&letters.into_iter().collect::<String>())?)
+    }
+
+    /// Same alphabet for the plaintext, but with a ciphertext written in other symbols
+    pub fn with_symbols(&self, symbols: Vec<char>) -> Self {
+        Alphabet {
+            letters: self.letters.clone(),
+            symbols: Some(symbols),
+        }
+    }
+
+    pub fn letters(&self) -> &[char] {
+        &self.letters
+    }
+
+    /// Letters that the ciphertext is written in, which are the letters of the alphabet unless symbols are used
+    pub fn cipher_letters(&self) -> &[char] {
+        self.symbols.as_deref().unwrap_or(&self.letters)
+    }
+
+    pub fn len(&self) -> usize {
+        self.letters.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.letters.is_empty()
+    }
+
+    pub fn contains(&self, c: char) -> bool {
+        self.letters.contains(&c)
+    }
+
+    /// Index of a letter in the alphabet, or `None` if it is not in the alphabet
+    pub fn index(&self, c: char) -> Option<u8> {
+        self.letters.iter().position(|&l| l == c).map(|i| i as u8)
+    }
+
+    /// Letter at an index in the alphabet
+    pub fn letter(&self, i: u8) -> char {
+        self.letters[i as usize]
+    }
+
+    /// Encode a word as alphabet indices, or `None` if it contains anything that is not in the alphabet
+    pub fn encode(&self, word: &str) -> Option<Vec<u8>> {
+        word.chars().map(|c| self.index(c)).collect()
+    }
+
+    /// Index of a letter in the cipher letters, or `None` if the ciphertext can't contain it
+    pub fn cipher_index(&self, c: char) -> Option<u8> {
+        self.cipher_letters()
+            .iter()
+            .position(|&l| l == c)
+            .map(|i| i as u8)
+    }
+
+    /// Cipher letter at an index in the cipher letters
+    pub fn cipher_letter(&self, i: u8) -> char {
+        self.cipher_letters()[i as usize]
+    }
+
+    /// Encode a cipher word as indices in the cipher letters
+    pub fn encode_cipher(&self, word: &str) -> Option<Vec<u8>> {
+        word.chars().map(|c| self.cipher_index(c)).collect()
+    }
+
+    /// Letters of the alphabet that a character stands for, or `None` if it is not a letter of this alphabet.
+    /// Uppercase letters become lowercase, and letters with accents that are not in the alphabet become their
+    /// base letter (example: 'É' -> "e" for latin, but "é" for dutch)
+    pub fn fold(&self, c: char) -> Option<String> {
+        let all_letters = |s: &str| !s.is_empty() && s.chars().all(|c| self.contains(c));
+
+        let lower = c.to_lowercase().collect::<String>();
+        if all_letters(&lower) {
+            return Some(lower);
+        }
+
+        let variant = VARIANTS
+            .iter()
+            .find(|&&(v, l)| lower == v.to_string() && self.contains(l));
+        if let Some(&(_, letter)) = variant {
+            return Some(letter.to_string());
+        }
+
+        let base = lower
+            .nfd()
+            .filter(|&c| !is_combining_mark(c))
+            .collect::<String>();
+        if all_letters(&base) {
+            return Some(base);
+        }
+
+        // Letters from other scripts, like 'æ' -> "ae"
+        let decoded = unidecode(&lower).to_lowercase();
+        if all_letters(&decoded) {
+            return Some(decoded);
+        }
+
+        None
+    }
+}
+impl Display for Alphabet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.letters.iter().collect::<String>().fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fold_letters() {
+        let latin = Alphabet::default();
+        assert_eq!(latin.fold('A').as_deref(), Some("a"));
+        assert_eq!(latin.fold('é').as_deref(), Some("e"));
+        assert_eq!(latin.fold('Æ').as_deref(), Some("ae"));
+        assert_eq!(latin.fold('1'), None);
+
+        let dutch = Alphabet::preset("dutch").unwrap();
+        assert_eq!(dutch.fold('É').as_deref(), Some("é"));
+        assert_eq!(dutch.fold('ô').as_deref(), Some("o"));
+
+        let greek = Alphabet::preset("greek").unwrap();
+        assert_eq!(greek.fold('Ά').as_deref(), Some("α"));
+        assert_eq!(greek.fold('ς').as_deref(), Some("σ"));
+        assert_eq!(greek.fold('a'), None);
+
+        let russian = Alphabet::preset("russian").unwrap();
+        assert_eq!(russian.len(), 33);
+        assert_eq!(russian.fold('Й').as_deref(), Some("й"));
+    }
+
+    #[test]
+    fn alphabet_from_wordlist() {
+        let alphabet = Alphabet::from_wordlist("привет\t10\nмир 5").unwrap();
+        assert_eq!(alphabet.to_string(), "веимпрт");
+        assert_eq!(alphabet.encode("мир"), Some(vec![3, 2, 5]));
+        assert_eq!(alphabet.encode("mир"), None);
+
+        assert!(Alphabet::new("ab1").is_err());
+        assert!(Alphabet::new(&"α".repeat(2)).is_err());
+    }
+}
@@ -0,0 +1,158 @@
+use std::{
+    fmt::Display,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        OnceLock,
+    },
+    time::{Duration, Instant},
+};
+
+/// Nodes between checks of the clock, because it is slower than counting
+const NODES_PER_CLOCK_CHECK: usize = 256;
+
+/// Why a search stopped before it was complete
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stop {
+    /// Found the maximum number of solutions
+    Solutions,
+    /// Ran out of time
+    Timeout,
+    /// Visited the maximum number of nodes in the search tree
+    Nodes,
+}
+impl Display for Stop {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Stop::Solutions => "solutions",
+            Stop::Timeout => "timeout",
+            Stop::Nodes => "nodes",
+        }
+        .fmt(f)
+    }
+}
+
+/// Limits on how much a search may do, shared by all threads that work on it.
+/// Solvers check it at every node of their search, and stop as soon as any limit is reached
+#[derive(Debug, Default)]
+pub struct Budget {
+    /// Most solutions to find
+    pub max_solutions: Option<usize>,
+    /// Time after which no more solutions are searched
+    pub deadline: Option<Instant>,
+    /// Most nodes of the search tree to visit
+    pub max_nodes: Option<usize>,
+    solutions: AtomicUsize,
+    nodes: AtomicUsize,
+    stopped: OnceLock<Stop>,
+}
+impl Budget {
+    /// Budget without any limits
+    pub fn unlimited() -> Self {
+        Budget::default()
+    }
+
+    /// Budget with limits, where the timeout starts counting now
+    pub fn new(
+        max_solutions: Option<usize>,
+        timeout: Option<Duration>,
+        max_nodes: Option<usize>,
+    ) -> Self {
+        Budget {
+            max_solutions,
+            deadline: timeout.map(|timeout| Instant::now() + timeout),
+            max_nodes,
+            ..Default::default()
+        }
+    }
+
+    /// Count a node of the search tree. Returns `false` if the search should stop
+    pub fn visit(&self) -> bool {
+        if self.stopped.get().is_some() {
+            return false;
+        }
+        if self.max_nodes.is_none() && self.deadline.is_none() {
+            // Nothing to count, and threads don't have to share the counter
+            return true;
+        }
+
+        let nodes = self.nodes.fetch_add(1, Ordering::Relaxed) + 1;
+        if self.max_nodes.is_some_and(|max| nodes > max) {
+            self.stop(Stop::Nodes);
+            return false;
+        }
+        !(nodes - 1).is_multiple_of(NODES_PER_CLOCK_CHECK) || !self.is_stopped()
+    }
+
+    /// Count a solution before sending it. Returns `false` if there were enough solutions already
+    pub fn take_solution(&self) -> bool {
+        if self.stopped.get().is_some() {
+            return false;
+        }
+
+        let solutions = self.solutions.fetch_add(1, Ordering::Relaxed) + 1;
+        match self.max_solutions {
+            Some(max) if solutions > max => false,
+            Some(max) => {
+                if solutions == max {
+                    self.stop(Stop::Solutions);
+                }
+                true
+            }
+            None => true,
+        }
+    }
+
+    /// Check if the search should stop, because a limit was reached or the time is up
+    pub fn is_stopped(&self) -> bool {
+        if self.stopped.get().is_some() {
+            return true;
+        }
+        if self
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            self.stop(Stop::Timeout);
+            return true;
+        }
+        false
+    }
+
+    /// Limit that stopped the search, or `None` if it was complete
+    pub fn stopped(&self) -> Option<Stop> {
+        self.stopped.get().copied()
+    }
+
+    fn stop(&self, reason: Stop) {
+        // Only the first reason is kept
+        let _ = self.stopped.set(reason);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn budget_limits() {
+        let budget = Budget::new(Some(2), None, Some(3));
+        assert!(budget.take_solution());
+        assert_eq!(budget.stopped(), None);
+        assert!(budget.take_solution());
+        assert_eq!(budget.stopped(), Some(Stop::Solutions));
+        assert!(!budget.take_solution());
+        assert!(!budget.visit());
+
+        let budget = Budget::new(None, None, Some(3));
+        assert!((0..3).all(|_| budget.visit()));
+        assert!(!budget.visit());
+        assert_eq!(budget.stopped(), Some(Stop::Nodes));
+
+        let budget = Budget::new(None, Some(Duration::ZERO), None);
+        assert!(budget.is_stopped());
+        assert_eq!(budget.stopped(), Some(Stop::Timeout));
+
+        let budget = Budget::unlimited();
+        assert!((0..1000).all(|_| budget.visit() && budget.take_solution()));
+        assert!(!budget.is_stopped());
+    }
+}
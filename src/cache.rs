@@ -1,37 +1,600 @@
 use std::{
-    collections::{HashMap, HashSet},
+    cmp::Reverse,
     error::Error,
-    fs::File,
-    io::{BufReader, BufWriter},
+    fmt::Display,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Seek, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
 };
 
-lazy_static! {
-    static ref CACHE_DIR: String = {
-        let mut path = dirs::cache_dir().unwrap();
-        path.push("sub-solver");
-        path.to_str().unwrap().to_string()
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    alphabet::Alphabet,
+    mapped::{write_mapped, MappedDictionary},
+    Dictionary, PatternLookup,
+};
+
+/// First bytes of every cache file, to recognize it
+const MAGIC: &[u8; 8] = b"SUBSOLVD";
+/// Increased when the layout of the cache file or `Dictionary` changes, so old cache files are not read
+const FORMAT_VERSION: u32 = 5;
+const CRATE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Environment variable with the directory to cache dictionaries in, overridden by `--cache-dir`
+pub const CACHE_DIR_ENV: &str = "SUB_SOLVER_CACHE_DIR";
+
+/// Why a cached dictionary could not be used
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CacheError {
+    /// The wordlist was not cached yet
+    Missing,
+    /// Written by another version, or for another wordlist
+    Stale(String),
+    /// Not a cache file, or damaged like when writing it was cut off
+    Corrupt(String),
+}
+impl Display for CacheError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CacheError::Missing => write!(f, "missing"),
+            CacheError::Stale(reason) => write!(f, "stale ({reason})"),
+            CacheError::Corrupt(reason) => write!(f, "corrupt ({reason})"),
+        }
+    }
+}
+impl Error for CacheError {}
+
+/// Information at the start of a cache file, which is checked before the dictionary is read
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Header {
+    /// Version of the program that wrote it
+    pub crate_version: String,
+    /// Letters that the words were cleaned into
+    pub alphabet: String,
+    /// File that the dictionary was made from, as it was when it was cached
+    pub wordlist: WordlistFile,
+    /// Hash of the contents of the wordlist, to check if it changed when it was modified
+    pub wordlist_hash: [u8; 16],
+    /// Number of patterns in the dictionary
+    pub entries: u64,
+}
+impl Header {
+    /// Read and check the header, and leave the reader at the start of the dictionary
+    pub fn read(reader: &mut impl Read) -> Result<Self, CacheError> {
+        let mut magic = [0; MAGIC.len()];
+        reader
+            .read_exact(&mut magic)
+            .map_err(|_| CacheError::Corrupt("file is too short".to_string()))?;
+        if &magic != MAGIC {
+            return Err(CacheError::Corrupt(
+                "no header, written by an older version or damaged".to_string(),
+            ));
+        }
+
+        // The version comes first, so the rest of the header may change in later versions
+        let format_version: u32 = bincode::deserialize_from(&mut *reader)
+            .map_err(|e| CacheError::Corrupt(e.to_string()))?;
+        if format_version != FORMAT_VERSION {
+            return Err(CacheError::Stale(format!(
+                "format version {format_version}, expected {FORMAT_VERSION}"
+            )));
+        }
+        let header: Header = bincode::deserialize_from(&mut *reader)
+            .map_err(|e| CacheError::Corrupt(e.to_string()))?;
+        if header.crate_version != CRATE_VERSION {
+            return Err(CacheError::Stale(format!(
+                "written by version {}, this is {CRATE_VERSION}",
+                header.crate_version
+            )));
+        }
+        Ok(header)
+    }
+
+    fn write(&self, writer: &mut impl Write) -> Result<(), Box<dyn Error>> {
+        writer.write_all(MAGIC)?;
+        bincode::serialize_into(&mut *writer, &FORMAT_VERSION)?;
+        bincode::serialize_into(&mut *writer, self)?;
+        Ok(())
+    }
+}
+
+/// Directory to cache dictionaries in: the given one, or "sub-solver" in the cache directory of the user.
+/// Returns `None` if there is no cache directory on this system
+pub fn cache_dir(custom: Option<&Path>) -> Option<PathBuf> {
+    match custom {
+        Some(path) => Some(path.to_path_buf()),
+        None => dirs::cache_dir().map(|path| path.join("sub-solver")),
+    }
+}
+
+/// Wordlist file that a dictionary is made from. Its cache is found by the path, and the size and time it was
+/// modified tell if it changed without having to read it
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct WordlistFile {
+    /// Absolute path to the file
+    pub path: PathBuf,
+    /// Size of the file in bytes
+    pub size: u64,
+    pub modified: SystemTime,
+}
+impl WordlistFile {
+    /// Look up the file, before reading it
+    pub fn new(path: &Path) -> io::Result<Self> {
+        let metadata = fs::metadata(path)?;
+        Ok(WordlistFile {
+            path: fs::canonicalize(path)?,
+            size: metadata.len(),
+            modified: metadata.modified()?,
+        })
+    }
+}
+
+/// Words are cleaned into the alphabet, so the same wordlist gives a different dictionary for every alphabet.
+/// `None` is the alphabet of all letters in the wordlist, which isn't known before it is read
+fn get_filename(dir: &Path, wordlist: &WordlistFile, alphabet: Option<&Alphabet>) -> PathBuf {
+    let mut context = md5::Context::new();
+    context.consume(alphabet.map_or("wordlist".to_string(), Alphabet::to_string));
+    context.consume("\n");
+    context.consume(wordlist.path.as_os_str().as_encoded_bytes());
+
+    dir.join(format!("{:x}.bin", context.compute()))
+}
+
+/// Load the cached dictionary of a wordlist, after checking that it was written by this version for the same wordlist.
+/// The wordlist is only read to compare its hash if it was modified, but has the same size.
+/// Returns the alphabet too, which is read from the header for the alphabet of all letters in the wordlist (`None`)
+pub fn load_cached_dictionary(
+    dir: &Path,
+    wordlist: &WordlistFile,
+    alphabet: Option<&Alphabet>,
+) -> Result<(MappedDictionary, Alphabet), CacheError> {
+    let path = get_filename(dir, wordlist, alphabet);
+    let file = match File::open(&path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Err(CacheError::Missing),
+        Err(e) => return Err(CacheError::Corrupt(e.to_string())),
+    };
+    let mut reader = BufReader::new(file);
+
+    let mut header = Header::read(&mut reader)?;
+    let same_alphabet = alphabet.is_none_or(|alphabet| header.alphabet == alphabet.to_string());
+    if header.wordlist.path != wordlist.path || !same_alphabet {
+        return Err(CacheError::Stale("made from another wordlist".to_string()));
+    }
+    let alphabet = match alphabet {
+        Some(alphabet) => alphabet.clone(),
+        None => Alphabet::new(&header.alphabet).map_err(CacheError::Corrupt)?,
     };
+    let touched = header.wordlist.modified != wordlist.modified;
+    let changed = header.wordlist.size != wordlist.size
+        || touched && hash_file(&wordlist.path).map_or(true, |hash| hash != header.wordlist_hash);
+    if changed {
+        return Err(CacheError::Stale("wordlist has changed".to_string()));
+    }
+
+    let start = reader
+        .stream_position()
+        .map_err(|e| CacheError::Corrupt(e.to_string()))?;
+
+    let dictionary =
+        MappedDictionary::open(reader.get_ref(), start).map_err(CacheError::Corrupt)?;
+    if dictionary.len() as u64 != header.entries {
+        return Err(CacheError::Corrupt(format!(
+            "{} patterns, expected {}",
+            dictionary.len(),
+            header.entries
+        )));
+    }
+
+    // Only a valid file is updated, so a damaged one isn't kept by `prune`
+    if let Ok(mut file) = File::options().write(true).open(&path) {
+        if touched {
+            // Same content, so remember the new time to not read the wordlist again next time.
+            // The header keeps its size, because only the time changed
+            header.wordlist.modified = wordlist.modified;
+            let _ = header.write(&mut file);
+        }
+        // Remember when it was last used, so `prune` keeps it
+        let _ = file.set_modified(SystemTime::now());
+    }
+    Ok((dictionary, alphabet))
 }
 
-fn get_filename(content: &str) -> String {
-    format!("{}/{:x}.bin", *CACHE_DIR, md5::compute(content))
+/// Hash of the bytes in a file, read a part at a time
+fn hash_file(path: &Path) -> io::Result<[u8; 16]> {
+    let mut context = md5::Context::new();
+    io::copy(&mut File::open(path)?, &mut context)?;
+    Ok(context.compute().0)
 }
 
-pub fn load_cached_dictionary(content: &str) -> Option<HashMap<String, HashSet<String>>> {
-    if let Ok(file) = File::open(get_filename(content)) {
-        let reader = BufReader::new(file);
-        bincode::deserialize_from(reader).ok()
-    } else {
-        None
+/// Reader that hashes all bytes that are read through it, so a wordlist can be hashed while loading it.
+/// The hash is of the file as it is stored, before decompressing it
+pub struct HashingReader<'a, R> {
+    inner: R,
+    context: &'a mut md5::Context,
+}
+impl<'a, R: Read> HashingReader<'a, R> {
+    pub fn new(inner: R, context: &'a mut md5::Context) -> Self {
+        HashingReader { inner, context }
+    }
+}
+impl<R: Read> Read for HashingReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.context.consume(&buf[..read]);
+        Ok(read)
     }
 }
 
+/// Save the dictionary of a wordlist with a header, in the format of `MappedDictionary`. It is written to a temporary file first,
+/// so an interrupted write never leaves half a cache file behind. With `from_wordlist`, the alphabet is all letters in the wordlist,
+/// and is saved so it can be loaded without reading the wordlist
 pub fn save_cached_dictionary(
-    content: &str,
-    dictionary: &HashMap<String, HashSet<String>>,
+    dir: &Path,
+    wordlist: &WordlistFile,
+    wordlist_hash: [u8; 16],
+    alphabet: &Alphabet,
+    from_wordlist: bool,
+    dictionary: &Dictionary,
 ) -> Result<(), Box<dyn Error>> {
-    std::fs::create_dir_all(&*CACHE_DIR)?; // Create folder if doesn't exist
-    let mut file = BufWriter::new(File::create(get_filename(content))?);
-    bincode::serialize_into(&mut file, dictionary)?;
+    fs::create_dir_all(dir)?; // Create folder if doesn't exist
+    let path = get_filename(dir, wordlist, (!from_wordlist).then_some(alphabet));
+    let temporary = path.with_extension(format!("{}.tmp", std::process::id()));
+
+    let header = Header {
+        crate_version: CRATE_VERSION.to_string(),
+        alphabet: alphabet.to_string(),
+        wordlist: wordlist.clone(),
+        wordlist_hash,
+        entries: dictionary.len() as u64,
+    };
+    let mut file = BufWriter::new(File::create(&temporary)?);
+    header.write(&mut file)?;
+    write_mapped(dictionary, &mut file)?;
+    file.into_inner()?.sync_all()?;
+
+    fs::rename(&temporary, &path)?;
     Ok(())
 }
+
+/// File with a cached dictionary
+#[derive(Debug, Clone)]
+pub struct CacheEntry {
+    pub path: PathBuf,
+    /// Size of the file in bytes
+    pub size: u64,
+    /// Last time the dictionary was saved or loaded
+    pub modified: SystemTime,
+    /// Header of the file, or why it can't be read by this version
+    pub header: Result<Header, CacheError>,
+}
+impl CacheEntry {
+    /// Time since the dictionary was last used
+    pub fn age(&self) -> Duration {
+        self.modified.elapsed().unwrap_or_default()
+    }
+}
+
+/// Whether a file is a cache file written by `save_cached_dictionary`: `Some(false)` for "<hash>.bin", and `Some(true)`
+/// for the temporary "<hash>.<pid>.tmp" that it is written to first, which is left behind if writing was interrupted
+fn cache_file_kind(path: &Path) -> Option<bool> {
+    let name = path.file_name()?.to_str()?;
+    let (hash, extension) = name.split_once('.')?;
+    if hash.len() != 32 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    match extension.strip_suffix(".tmp") {
+        None => (extension == "bin").then_some(false),
+        Some(pid) => (!pid.is_empty() && pid.chars().all(|c| c.is_ascii_digit())).then_some(true),
+    }
+}
+
+/// All cached dictionaries in the directory, most recently used first. A missing directory has no entries
+pub fn list_entries(dir: &Path) -> io::Result<Vec<CacheEntry>> {
+    let read_dir = match fs::read_dir(dir) {
+        Ok(read_dir) => read_dir,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    let mut entries = Vec::new();
+    for entry in read_dir {
+        let entry = entry?;
+        let path = entry.path();
+        let Some(temporary) = cache_file_kind(&path) else {
+            continue;
+        };
+        let metadata = entry.metadata()?;
+        if !metadata.is_file() {
+            continue;
+        }
+        let header = if temporary {
+            Err(CacheError::Corrupt("unfinished write".to_string()))
+        } else {
+            File::open(&path)
+                .map_err(|e| CacheError::Corrupt(e.to_string()))
+                .and_then(|file| Header::read(&mut BufReader::new(file)))
+        };
+        entries.push(CacheEntry {
+            size: metadata.len(),
+            modified: metadata.modified()?,
+            header,
+            path,
+        });
+    }
+    entries.sort_by_key(|entry| Reverse(entry.modified));
+    Ok(entries)
+}
+
+/// Remove the cached dictionaries that were not used for longer than `older_than`, or all of them if it is `None`.
+/// Returns the entries that were removed
+pub fn remove_entries(dir: &Path, older_than: Option<Duration>) -> io::Result<Vec<CacheEntry>> {
+    let mut removed = list_entries(dir)?;
+    removed.retain(|entry| older_than.is_none_or(|age| entry.age() > age));
+    for entry in &removed {
+        fs::remove_file(&entry.path)?;
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Load from the cache, and read the whole dictionary to compare it
+    fn load(
+        dir: &Path,
+        wordlist: &WordlistFile,
+        alphabet: &Alphabet,
+    ) -> Result<Dictionary, CacheError> {
+        let (mapped, _) = load_cached_dictionary(dir, wordlist, Some(alphabet))?;
+        Ok(mapped
+            .patterns()
+            .map(|(pattern, words)| {
+                let words = words
+                    .into_iter()
+                    .map(|(word, count)| (word.to_string(), count));
+                (pattern.to_string(), words.collect())
+            })
+            .collect())
+    }
+
+    /// Empty directory for a test, with a wordlist in it
+    fn test_dir(name: &str, content: &str) -> (PathBuf, WordlistFile) {
+        let dir = std::env::temp_dir().join(format!("sub-solver-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("wordlist.txt");
+        fs::write(&path, content).unwrap();
+        (dir, WordlistFile::new(&path).unwrap())
+    }
+
+    #[test]
+    fn manage_entries() {
+        let content = "a\nfew\nwords";
+        let (dir, wordlist) = test_dir("entries", content);
+        let alphabet = Alphabet::default();
+        assert!(list_entries(&dir).unwrap().is_empty());
+
+        let dictionary = crate::load_wordlist(content, &alphabet);
+        save_cached_dictionary(
+            &dir,
+            &wordlist,
+            md5::compute(content).0,
+            &alphabet,
+            false,
+            &dictionary,
+        )
+        .unwrap();
+        let russian = Alphabet::preset("russian").unwrap();
+        save_cached_dictionary(
+            &dir,
+            &wordlist,
+            md5::compute(content).0,
+            &russian,
+            false,
+            &Dictionary::new(),
+        )
+        .unwrap();
+        fs::write(dir.join("notes.txt"), "not a cache file").unwrap();
+        fs::write(dir.join("notes.1.tmp"), "not a cache file").unwrap();
+        // Left behind by a crash while saving
+        let unfinished = get_filename(&dir, &wordlist, Some(&Alphabet::preset("greek").unwrap()))
+            .with_extension("1234.tmp");
+        fs::write(&unfinished, "SUBSOLVD").unwrap();
+
+        let entries = list_entries(&dir).unwrap();
+        assert_eq!(entries.len(), 3);
+        for entry in &entries {
+            assert_eq!(entry.header.is_ok(), entry.path != unfinished);
+        }
+        assert_eq!(load(&dir, &wordlist, &alphabet), Ok(dictionary));
+
+        assert!(remove_entries(&dir, Some(Duration::from_secs(3600)))
+            .unwrap()
+            .is_empty());
+        assert_eq!(remove_entries(&dir, None).unwrap().len(), 3);
+        assert!(dir.join("notes.1.tmp").exists());
+        assert!(list_entries(&dir).unwrap().is_empty());
+        assert!(dir.join("notes.txt").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_invalid_files() {
+        let content = "a\nfew\nwords";
+        let (dir, wordlist) = test_dir("invalid", content);
+        let alphabet = Alphabet::default();
+        let path = get_filename(&dir, &wordlist, Some(&alphabet));
+        assert_eq!(load(&dir, &wordlist, &alphabet), Err(CacheError::Missing));
+
+        let dictionary = crate::load_wordlist(content, &alphabet);
+        save_cached_dictionary(
+            &dir,
+            &wordlist,
+            md5::compute(content).0,
+            &alphabet,
+            false,
+            &dictionary,
+        )
+        .unwrap();
+        let bytes = fs::read(&path).unwrap();
+
+        // Cut off halfway
+        fs::write(&path, &bytes[..bytes.len() / 2]).unwrap();
+        assert!(matches!(
+            load(&dir, &wordlist, &alphabet),
+            Err(CacheError::Corrupt(_))
+        ));
+
+        // Written before there was a header
+        fs::write(&path, b"0123456789").unwrap();
+        assert!(matches!(
+            load(&dir, &wordlist, &alphabet),
+            Err(CacheError::Corrupt(_))
+        ));
+
+        // Another format version
+        let mut old = bytes.clone();
+        old[MAGIC.len()] += 1;
+        fs::write(&path, &old).unwrap();
+        assert!(matches!(
+            load(&dir, &wordlist, &alphabet),
+            Err(CacheError::Stale(_))
+        ));
+
+        // Data after the dictionary
+        let mut longer = bytes.clone();
+        longer.push(0);
+        fs::write(&path, &longer).unwrap();
+        assert!(matches!(
+            load(&dir, &wordlist, &alphabet),
+            Err(CacheError::Corrupt(_))
+        ));
+
+        fs::write(&path, &bytes).unwrap();
+        assert_eq!(load(&dir, &wordlist, &alphabet), Ok(dictionary));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn detects_changed_wordlist() {
+        let content = "a\nfew\nwords";
+        let (dir, wordlist) = test_dir("changed", content);
+        let alphabet = Alphabet::default();
+        let mut hash = md5::Context::new();
+        let reader = HashingReader::new(File::open(&wordlist.path).unwrap(), &mut hash);
+        let dictionary = crate::read_wordlist(BufReader::new(reader), &alphabet, |_| true).unwrap();
+        assert_eq!(dictionary, crate::load_wordlist(content, &alphabet));
+        let hash = hash.compute().0;
+        save_cached_dictionary(&dir, &wordlist, hash, &alphabet, false, &dictionary).unwrap();
+
+        // Only modified again, so the hash is checked and the new time is remembered
+        let touched = WordlistFile {
+            modified: wordlist.modified + Duration::from_secs(60),
+            ..wordlist.clone()
+        };
+        File::options()
+            .write(true)
+            .open(&wordlist.path)
+            .unwrap()
+            .set_modified(touched.modified)
+            .unwrap();
+        assert_eq!(load(&dir, &touched, &alphabet), Ok(dictionary.clone()));
+        let entries = list_entries(&dir).unwrap();
+        assert_eq!(entries[0].header.as_ref().unwrap().wordlist, touched);
+
+        // Same size, but other words
+        fs::write(&wordlist.path, "a\nfew\nwards").unwrap();
+        let changed = WordlistFile::new(&wordlist.path).unwrap();
+        assert!(matches!(
+            load(&dir, &changed, &alphabet),
+            Err(CacheError::Stale(_))
+        ));
+
+        // Another size is not read at all
+        let longer = WordlistFile {
+            size: wordlist.size + 1,
+            ..touched
+        };
+        assert!(matches!(
+            load(&dir, &longer, &alphabet),
+            Err(CacheError::Stale(_))
+        ));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn remembers_wordlist_alphabet() {
+        let content = "año\nça\nwords";
+        let (dir, wordlist) = test_dir("alphabet", content);
+        let alphabet = Alphabet::from_wordlist(content).unwrap();
+        let dictionary = crate::load_wordlist(content, &alphabet);
+        let hash = md5::compute(content).0;
+        save_cached_dictionary(&dir, &wordlist, hash, &alphabet, true, &dictionary).unwrap();
+
+        // Found without knowing the letters
+        let (mapped, loaded) = load_cached_dictionary(&dir, &wordlist, None).unwrap();
+        assert_eq!(loaded, alphabet);
+        assert_eq!(mapped.len(), dictionary.len());
+        // Not the same entry as when the letters are given
+        assert_eq!(load(&dir, &wordlist, &alphabet), Err(CacheError::Missing));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn keeps_damaged_files_old() {
+        let content = "a\nfew\nwords";
+        let (dir, wordlist) = test_dir("damaged", content);
+        let alphabet = Alphabet::default();
+        let path = get_filename(&dir, &wordlist, Some(&alphabet));
+        let dictionary = crate::load_wordlist(content, &alphabet);
+        let hash = md5::compute(content).0;
+        save_cached_dictionary(&dir, &wordlist, hash, &alphabet, false, &dictionary).unwrap();
+
+        // Cut off after the header, and last used long ago
+        let bytes = fs::read(&path).unwrap();
+        fs::write(&path, &bytes[..bytes.len() - 1]).unwrap();
+        let used = SystemTime::now() - Duration::from_secs(7200);
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(used)
+            .unwrap();
+
+        // Touched wordlist would update the header of a valid file
+        let touched = WordlistFile {
+            modified: wordlist.modified + Duration::from_secs(60),
+            ..wordlist.clone()
+        };
+        File::options()
+            .write(true)
+            .open(&wordlist.path)
+            .unwrap()
+            .set_modified(touched.modified)
+            .unwrap();
+        assert!(matches!(
+            load(&dir, &touched, &alphabet),
+            Err(CacheError::Corrupt(_))
+        ));
+        let entries = list_entries(&dir).unwrap();
+        assert_eq!(entries[0].header.as_ref().unwrap().wordlist, wordlist);
+        assert_eq!(entries[0].modified, used);
+        assert_eq!(
+            remove_entries(&dir, Some(Duration::from_secs(3600)))
+                .unwrap()
+                .len(),
+            1
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
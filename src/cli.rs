@@ -1,11 +1,19 @@
-use std::path::PathBuf;
+use std::{ffi::OsString, path::PathBuf, time::Duration};
 
-use clap::{ArgGroup, Parser};
+use clap::{
+    error::ErrorKind, parser::ValueSource, ArgGroup, CommandFactory, FromArgMatches, Parser,
+    Subcommand,
+};
+
+use crate::{cache::CACHE_DIR_ENV, output::Format};
 
 /// Substitution Cipher Solver
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Clone)]
 #[command(name = "sub-solver")]
 pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     #[clap(flatten)]
     pub ciphertext: Ciphertext,
 
@@ -13,27 +21,242 @@ pub struct Args {
     #[arg(short, long)]
     pub wordlist: Option<String>,
 
-    /// Starting key, letter mapping (default: empty, example: "a:b,c:d,e:f", "ab,cd,ef", "b?d?f?????????????????????")
+    /// Alphabet of the ciphertext: a preset (latin, dutch, greek, russian), its letters in order, or "wordlist" to use all letters in the wordlist (default: latin)
+    #[arg(short, long)]
+    pub alphabet: Option<String>,
+
+    /// Starting key, letter mapping (default: empty, example: "a:b,c:d,e:f", "ab,cd,ef", "b?d?f?????????????????????", "12:e,5:t" for symbols, "a!e,!z" to exclude letters)
     #[arg(short, long)]
     pub key: Option<String>,
 
+    /// Known plaintext somewhere in the ciphertext, every place it fits is tried (example: "attack at dawn")
+    #[arg(short, long, conflicts_with = "statistical")]
+    pub crib: Option<String>,
+
+    /// Solve a ciphertext of symbols, where every character that is not a space is a cipher symbol (default: false)
+    #[arg(long)]
+    pub symbols: bool,
+
+    /// Split the symbols in words on this delimiter, like "-" for "12-5-7 3-1" (implies --symbols)
+    #[arg(short, long)]
+    pub delimiter: Option<String>,
+
+    /// Maximum number of words that may be unknown, like names not in the wordlist. Only words where no candidate leads to a solution are skipped
+    #[arg(short, long, default_value_t = 0)]
+    pub unknown: usize,
+
+    /// Ignore spaces in the ciphertext, and search for the word boundaries too (default: false)
+    #[arg(short, long, conflicts_with = "unknown")]
+    pub ignore_spaces: bool,
+
+    /// Allow multiple cipher letters to be the same plaintext letter, like in homophonic ciphers (default: false)
+    #[arg(long, conflicts_with = "ignore_spaces")]
+    pub homophonic: bool,
+
+    /// Most cipher letters that may be the same plaintext letter (implies --homophonic, default: no limit)
+    #[arg(long, value_name = "N", conflicts_with = "ignore_spaces")]
+    pub max_homophones: Option<usize>,
+
+    /// Use the statistical solver instead of the wordlist, for longer ciphertexts (default: false)
+    #[arg(long, conflicts_with_all = ["unknown", "ignore_spaces"])]
+    pub statistical: bool,
+
+    /// Don't fall back to the statistical solver if the wordlist finds no solutions (default: false)
+    #[arg(long, conflicts_with = "statistical")]
+    pub no_fallback: bool,
+
+    /// Number of random restarts for the statistical solver
+    #[arg(long, default_value_t = 20)]
+    pub restarts: usize,
+
+    /// Number of threads to search with, 0 to use all cores (with --batch: number of inputs to solve at the same time)
+    #[arg(short = 'j', long, default_value_t = 1)]
+    pub threads: usize,
+
+    /// Print solutions in the same order as with one thread (default: false)
+    #[arg(long)]
+    pub ordered: bool,
+
+    /// Stop searching after finding N solutions (with --batch: per input)
+    #[arg(long, value_name = "N")]
+    pub max_solutions: Option<usize>,
+
+    /// Stop searching after this many seconds (with --batch: per input)
+    #[arg(long, value_name = "SECONDS")]
+    pub timeout: Option<f64>,
+
+    /// Stop searching after visiting N nodes of the search tree, for a limit that doesn't depend on the machine
+    #[arg(long, value_name = "N")]
+    pub max_nodes: Option<usize>,
+
+    /// Keep the case, punctuation and line breaks of the ciphertext in the plaintext (default: false)
+    #[arg(short, long, conflicts_with_all = ["symbols", "delimiter", "ignore_spaces"])]
+    pub preserve: bool,
+
+    /// Solve step by step in the terminal, by pinning and forbidding letters (default: false)
+    #[arg(
+        short = 'I',
+        long,
+        conflicts_with_all = ["statistical", "ignore_spaces", "symbols", "delimiter", "crib", "sort", "top"]
+    )]
+    pub interactive: bool,
+
+    /// Solve every line of the ciphertext, or every file in the --file directory, as a separate input (default: false)
+    #[arg(short, long, conflicts_with = "interactive")]
+    pub batch: bool,
+
     /// Fill in unknowns in solution with random unused letters (default: false)
     #[arg(short = 'F', long)]
     pub fill_key: bool,
 
+    /// Sort solutions by score, most likely first (default: false)
+    #[arg(short = 'S', long)]
+    pub sort: bool,
+
+    /// Only print the K highest scoring solutions (implies --sort)
+    #[arg(short, long, value_name = "K")]
+    pub top: Option<usize>,
+
+    /// Output format of the solutions
+    #[arg(long, value_enum, default_value_t = Format::Text)]
+    pub format: Format,
+
     /// Disable dictionary cache (default: false)
     #[arg(short, long)]
     pub no_cache: bool,
+
+    /// Directory to cache dictionaries in (default: "sub-solver" in the cache directory of the user)
+    #[arg(long, global = true, env = CACHE_DIR_ENV)]
+    pub cache_dir: Option<PathBuf>,
+}
+
+impl Args {
+    /// Parse the arguments of the program, and exit with the error if they are invalid
+    pub fn parse_args() -> Self {
+        Args::try_parse_args_from(std::env::args_os()).unwrap_or_else(|e| e.exit())
+    }
+
+    /// Parse arguments, where only global arguments like `--cache-dir` can be used together with a subcommand
+    pub fn try_parse_args_from(
+        args: impl IntoIterator<Item = impl Into<OsString> + Clone>,
+    ) -> Result<Self, clap::Error> {
+        let mut command = Args::command();
+        let matches = command.try_get_matches_from_mut(args)?;
+
+        if let Some((name, _)) = matches.subcommand() {
+            let solving = command.get_arguments().find(|arg| {
+                !arg.is_global_set()
+                    && matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine)
+            });
+            if let Some(arg) = solving {
+                let arg = arg.get_long().unwrap_or(arg.get_id().as_str());
+                return Err(command.error(
+                    ErrorKind::ArgumentConflict,
+                    format!("--{arg} can't be used with the '{name}' subcommand"),
+                ));
+            }
+        }
+
+        Args::from_arg_matches(&matches).map_err(|e| e.format(&mut command))
+    }
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Manage the cached dictionaries of wordlists
+    #[command(subcommand)]
+    Cache(CacheCommand),
 }
 
-#[derive(Parser, Debug)]
-#[clap(group = ArgGroup::new("ciphertext").required(true).multiple(false))]
+#[derive(Subcommand, Debug, Clone)]
+pub enum CacheCommand {
+    /// List all cached dictionaries, most recently used first
+    List,
+    /// Show where the cache is, and how much space it takes
+    Info,
+    /// Remove all cached dictionaries
+    Clear,
+    /// Remove cached dictionaries that were not used for a while
+    Prune {
+        /// Remove dictionaries that were last used longer ago than this (example: "30d", "12h", "90m")
+        #[arg(long, value_name = "AGE", value_parser = parse_age)]
+        older_than: Duration,
+    },
+}
+
+/// Parse an age like "30d" in days, hours, minutes or seconds. A number without a unit is in days
+fn parse_age(age: &str) -> Result<Duration, String> {
+    let (number, seconds) = match age.char_indices().last() {
+        Some((i, 'd')) => (&age[..i], 24 * 60 * 60),
+        Some((i, 'h')) => (&age[..i], 60 * 60),
+        Some((i, 'm')) => (&age[..i], 60),
+        Some((i, 's')) => (&age[..i], 1),
+        _ => (age, 24 * 60 * 60),
+    };
+    let invalid = || format!("Invalid age {age:?}, expected a number with d, h, m or s");
+    let number: u64 = number.trim().parse().map_err(|_| invalid())?;
+    // Too long ages would overflow into a shorter one
+    let seconds = number.checked_mul(seconds).ok_or_else(invalid)?;
+    Ok(Duration::from_secs(seconds))
+}
+
+#[derive(Parser, Debug, Clone)]
+#[clap(group = ArgGroup::new("ciphertext").multiple(false))]
 pub struct Ciphertext {
     /// Ciphertext string to solve
     #[clap(group = "ciphertext", short, long)]
     pub string: Option<String>,
 
-    /// Path to the ciphertext file
+    /// Path to the ciphertext file, or "-" for stdin (default: read from stdin if neither is given)
     #[clap(group = "ciphertext", short, long)]
     pub file: Option<PathBuf>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ages() {
+        assert_eq!(parse_age("30d"), Ok(Duration::from_secs(30 * 24 * 60 * 60)));
+        assert_eq!(parse_age("12h"), Ok(Duration::from_secs(12 * 60 * 60)));
+        assert_eq!(parse_age("90m"), Ok(Duration::from_secs(90 * 60)));
+        assert_eq!(parse_age("5s"), Ok(Duration::from_secs(5)));
+        assert_eq!(parse_age("7"), Ok(Duration::from_secs(7 * 24 * 60 * 60)));
+        assert!(parse_age("d").is_err());
+        assert!(parse_age("-1d").is_err());
+        assert!(parse_age("").is_err());
+        assert!(parse_age("999999999999999999d").is_err());
+        assert!(parse_age("99999999999999999999s").is_err());
+    }
+
+    #[test]
+    fn cache_subcommand_args() {
+        let args = Args::try_parse_args_from(["sub-solver", "--cache-dir", "dir", "cache", "list"])
+            .unwrap();
+        assert!(matches!(
+            args.command,
+            Some(Command::Cache(CacheCommand::List))
+        ));
+        assert_eq!(args.cache_dir, Some(PathBuf::from("dir")));
+
+        let args =
+            Args::try_parse_args_from(["sub-solver", "cache", "--cache-dir", "dir", "clear"])
+                .unwrap();
+        assert_eq!(args.cache_dir, Some(PathBuf::from("dir")));
+
+        let error =
+            Args::try_parse_args_from(["sub-solver", "-s", "abc", "cache", "list"]).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::ArgumentConflict);
+        assert!(Args::try_parse_args_from(["sub-solver", "-s", "abc", "-u", "1"]).is_ok());
+    }
+
+    #[test]
+    fn preserve_conflicts() {
+        // The words that are found without spaces can't be put back into the original formatting
+        let error = Args::try_parse_args_from(["sub-solver", "-s", "Xcbt, tloap!", "-p", "-i"])
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::ArgumentConflict);
+        assert!(Args::try_parse_args_from(["sub-solver", "-s", "X cbt, tloap!", "-p"]).is_ok());
+    }
+}
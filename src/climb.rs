@@ -0,0 +1,292 @@
+use std::{
+    collections::HashMap,
+    sync::{mpsc, Arc},
+};
+
+use crate::{
+    budget::Budget,
+    key::{Exclusions, MAX_LETTERS},
+    score::NgramModel,
+    solve::Solution,
+};
+
+/// Statistical solver using simulated annealing on quadgram fitness, with random restarts.
+/// Does not need the words to be in the wordlist, but does need a longer ciphertext
+pub struct HillClimber {
+    pub ngrams: NgramModel,
+    /// Number of times to start over from a random key
+    pub restarts: usize,
+    /// Number of letter swaps to try per restart
+    pub iterations: usize,
+    /// Most cipher letters that may get the same plaintext letter, for homophonic ciphers
+    pub max_homophones: usize,
+    /// Plaintext letters that cipher letters can't be, which keys never use
+    pub exclusions: Exclusions,
+    /// Limits the solutions that are sent, and counts every swap that is tried as a node
+    pub budget: Arc<Budget>,
+    rng: fastrand::Rng,
+}
+impl HillClimber {
+    pub fn new(ngrams: NgramModel) -> Self {
+        HillClimber {
+            ngrams,
+            restarts: 20,
+            iterations: 20_000,
+            max_homophones: 1,
+            exclusions: Exclusions::default(),
+            budget: Arc::new(Budget::unlimited()),
+            rng: fastrand::Rng::new(),
+        }
+    }
+
+    /// Use a fixed seed for the random keys, to get the same results every time
+    pub fn seed(&mut self, seed: u64) {
+        self.rng = fastrand::Rng::with_seed(seed);
+    }
+
+    /// Send a solution every time a restart finds a better key than before, so the last one is the best
+    pub fn solve(
+        &mut self,
+        ciphertext: &str,
+        starting_key: HashMap<char, char>,
+        tx: Option<&mpsc::Sender<Solution>>,
+    ) {
+        let alphabet = self.ngrams.alphabet.clone();
+        // Words as alphabet indices, because quadgrams are only counted inside of words
+        let words = ciphertext
+            .split_whitespace()
+            .map(|word| {
+                word.chars()
+                    .filter_map(|c| alphabet.cipher_index(c))
+                    .map(usize::from)
+                    .collect::<Vec<_>>()
+            })
+            .filter(|word| !word.is_empty())
+            .collect::<Vec<_>>();
+        let used = words
+            .iter()
+            .flatten()
+            .fold([false; MAX_LETTERS], |mut used, &c| {
+                used[c] = true;
+                used
+            });
+
+        let mut pinned = [false; MAX_LETTERS];
+        let mut pinned_plain = [false; MAX_LETTERS];
+        let mut base_key = [0; MAX_LETTERS];
+        for (a, b) in &starting_key {
+            if let (Some(a), Some(b)) = (alphabet.cipher_index(*a), alphabet.index(*b)) {
+                let (a, b) = (a as usize, b as usize);
+                base_key[a] = b;
+                pinned[a] = true;
+                pinned_plain[b] = true;
+            }
+        }
+        // Cipher letters that may be swapped, and plaintext letters that are still free to give them.
+        // Letters in the text come first, because they have to be given a plaintext letter that isn't excluded
+        let mut free = (0..alphabet.cipher_letters().len())
+            .filter(|&c| !pinned[c])
+            .collect::<Vec<_>>();
+        free.sort_by_key(|&c| !used[c]);
+        let allowed = self.exclusions.allowed(&alphabet);
+        // Letters that are not in the text can be anything, because they are never shown
+        let fits = |c: usize, p: usize| !used[c] || allowed[c].contains(p as u8);
+        // Every plaintext letter can be given out as many times as it may have homophones
+        let free_plain = (0..alphabet.len())
+            .filter(|&p| !pinned_plain[p])
+            .flat_map(|p| std::iter::repeat_n(p, self.max_homophones.clamp(1, free.len().max(1))))
+            .collect::<Vec<_>>();
+        if free.len() < 2 {
+            return;
+        }
+
+        let total_letters = words.iter().map(|w| w.len()).sum::<usize>();
+        let mut best_fitness = f64::NEG_INFINITY;
+
+        for _ in 0..self.restarts {
+            if self.budget.is_stopped() {
+                break;
+            }
+            let mut key = base_key;
+            // Plaintext letters that no cipher letter has (yet), which can be swapped in
+            let mut spare = free_plain.clone();
+            self.rng.shuffle(&mut spare);
+            for &c in &free {
+                if let Some(i) = spare.iter().position(|&p| fits(c, p)) {
+                    key[c] = spare.swap_remove(i);
+                }
+            }
+            // This order of letters ran out of letters that fit, so the next restart tries another
+            if free.iter().any(|&c| !fits(c, key[c])) {
+                continue;
+            }
+
+            let mut fitness = self.fitness(&words, &key);
+            let (mut restart_key, mut restart_fitness) = (key, fitness);
+
+            for i in 0..self.iterations {
+                if !self.budget.visit() {
+                    break;
+                }
+                // Temperature decreases linearly, scaled to the length of the text
+                let temperature =
+                    (1.0 - i as f64 / self.iterations as f64) * total_letters as f64 / 50.0;
+
+                let a = free[self.rng.usize(..free.len())];
+                // Swap with another cipher letter, or give it a spare plaintext letter
+                let j = self.rng.usize(..free.len() + spare.len());
+                if j < free.len() {
+                    let b = free[j];
+                    if a == b || (!used[a] && !used[b]) || !fits(a, key[b]) || !fits(b, key[a]) {
+                        continue;
+                    }
+                    key.swap(a, b);
+                } else {
+                    if !used[a] || !fits(a, spare[j - free.len()]) {
+                        continue;
+                    }
+                    std::mem::swap(&mut key[a], &mut spare[j - free.len()]);
+                }
+                let new_fitness = self.fitness(&words, &key);
+                let delta = new_fitness - fitness;
+
+                if delta >= 0.0
+                    || (temperature > 0.0 && self.rng.f64() < (delta / temperature).exp())
+                {
+                    fitness = new_fitness;
+                    if fitness > restart_fitness {
+                        (restart_key, restart_fitness) = (key, fitness);
+                    }
+                } else if j < free.len() {
+                    key.swap(a, free[j]);
+                } else {
+                    std::mem::swap(&mut key[a], &mut spare[j - free.len()]);
+                }
+            }
+
+            if restart_fitness > best_fitness {
+                if !self.budget.take_solution() {
+                    break;
+                }
+                best_fitness = restart_fitness;
+
+                let mut solution = Solution::new(
+                    (0..alphabet.cipher_letters().len())
+                        .filter(|&c| used[c] || pinned[c])
+                        .map(|c| {
+                            (
+                                alphabet.cipher_letter(c as u8),
+                                alphabet.letter(restart_key[c] as u8),
+                            )
+                        })
+                        .collect(),
+                );
+                solution.score = Some(restart_fitness);
+                solution.alphabet = alphabet.clone();
+                if let Some(tx) = tx {
+                    tx.send(solution).unwrap();
+                }
+            }
+        }
+    }
+
+    fn fitness(&self, words: &[Vec<usize>], key: &[usize; MAX_LETTERS]) -> f64 {
+        words
+            .iter()
+            .map(|word| {
+                let plain = word.iter().map(|&c| key[c]).collect::<Vec<_>>();
+                self.ngrams.fitness_indices(&plain)
+            })
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{alphabet::Alphabet, budget::Stop, input::clean_input, load_wordlist};
+
+    use super::*;
+
+    #[test]
+    fn can_climb() {
+        let plaintext = "the old farmer watches from the porch of his house and thinks about the weather that is coming over the \
+            hills tomorrow morning before the sun rises above the mountains in the east and \
+            the birds start singing their songs in the trees behind the barn where the cows sleep";
+        let key = "qwertyuiopasdfghjklzxcvbnm";
+        let alphabet = Alphabet::default();
+        let ciphertext = clean_input(plaintext, &alphabet)
+            .chars()
+            .map(|c| match alphabet.index(c) {
+                Some(i) => key.chars().nth(i as usize).unwrap(),
+                None => c,
+            })
+            .collect::<String>();
+
+        let dictionary = load_wordlist(include_str!("../wordlist/english.txt"), &alphabet);
+        let mut climber = HillClimber::new(NgramModel::from_dictionary(&dictionary, &alphabet));
+        climber.seed(1337);
+        climber.restarts = 5;
+
+        let (tx, rx) = mpsc::channel();
+        climber.solve(&ciphertext, HashMap::new(), Some(&tx));
+        drop(tx);
+
+        let best = rx.iter().last().unwrap();
+        assert_eq!(best.apply(&ciphertext), clean_input(plaintext, &alphabet));
+    }
+
+    #[test]
+    fn climbs_with_exclusions() {
+        let alphabet = Alphabet::default();
+        let dictionary = load_wordlist(include_str!("../wordlist/english.txt"), &alphabet);
+        let mut climber = HillClimber::new(NgramModel::from_dictionary(&dictionary, &alphabet));
+        climber.seed(1337);
+        climber.restarts = 5;
+        climber.iterations = 2_000;
+        climber.exclusions = Exclusions {
+            pairs: vec![('z', 'e')],
+            absent: vec!['t'],
+        };
+
+        let (tx, rx) = mpsc::channel();
+        climber.solve("zzzzq qqqz", HashMap::new(), Some(&tx));
+        drop(tx);
+
+        let solutions = rx.iter().collect::<Vec<_>>();
+        assert!(!solutions.is_empty());
+        for solution in solutions {
+            assert_ne!(solution.key[&'z'], 'e');
+            assert!(!solution.key.values().any(|&p| p == 't'));
+        }
+    }
+
+    #[test]
+    fn climbs_within_budget() {
+        let alphabet = Alphabet::default();
+        let dictionary = load_wordlist(include_str!("../wordlist/english.txt"), &alphabet);
+        let ciphertext = "xliv evi jiaiv asvhw xlex gsqi jvsq xli wecqi tpegi";
+        let climb = |budget: Budget| {
+            let mut climber = HillClimber::new(NgramModel::from_dictionary(&dictionary, &alphabet));
+            climber.seed(1337);
+            climber.restarts = 10;
+            climber.iterations = 2_000;
+            climber.budget = Arc::new(budget);
+
+            let (tx, rx) = mpsc::channel();
+            climber.solve(ciphertext, HashMap::new(), Some(&tx));
+            drop(tx);
+            (rx.iter().count(), climber.budget.stopped())
+        };
+
+        assert!(climb(Budget::unlimited()).0 > 1);
+        assert_eq!(
+            climb(Budget::new(Some(1), None, None)),
+            (1, Some(Stop::Solutions))
+        );
+        // Not even one restart finishes
+        assert_eq!(
+            climb(Budget::new(None, None, Some(1_000))),
+            (0, Some(Stop::Nodes))
+        );
+    }
+}
@@ -0,0 +1,128 @@
+use std::{collections::HashMap, fmt::Display};
+
+use crate::{alphabet::Alphabet, key::Key};
+
+/// Position of a crib (known piece of plaintext) in the ciphertext, and the key that follows from it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Placement {
+    /// Index of the first cipher word the crib is on, or of the first letter if spaces are ignored
+    pub position: usize,
+    /// If `position` counts letters of the ciphertext without spaces, instead of words
+    pub ignore_spaces: bool,
+    /// Starting key together with the letters of the crib
+    pub key: HashMap<char, char>,
+}
+impl Display for Placement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let unit = if self.ignore_spaces { "letter" } else { "word" };
+        write!(f, "{unit} {}", self.position + 1)
+    }
+}
+
+/// Try every place in the cleaned ciphertext where the crib could be, and keep the ones that fit the starting key.
+/// The crib covers whole cipher words, or any letters in a row if `ignore_spaces` is set
+///
+/// ```rust
+/// use sub_solver::{alphabet::Alphabet, crib::place_crib};
+///
+/// let alphabet = Alphabet::default();
+/// let placements = place_crib("x cbt tloap", "few", &alphabet, &Default::default(), 1, false).unwrap();
+/// assert_eq!(placements.len(), 1);
+/// assert_eq!(placements[0].to_string(), "word 2");
+/// assert_eq!(placements[0].key[&'c'], 'f');
+/// ```
+pub fn place_crib(
+    ciphertext: &str,
+    crib: &str,
+    alphabet: &Alphabet,
+    starting_key: &HashMap<char, char>,
+    max_homophones: usize,
+    ignore_spaces: bool,
+) -> Result<Vec<Placement>, String> {
+    let crib_words = crib
+        .split_whitespace()
+        .map(|word| alphabet.encode(word))
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| format!("Crib {crib:?} has letters that are not in the alphabet"))?;
+    if crib_words.is_empty() {
+        return Err("Crib can't be empty".to_string());
+    }
+    let key = Key::from_map(starting_key, alphabet, max_homophones)
+        .ok_or("Starting key maps too many letters to the same letter")?;
+
+    let cipher_words = ciphertext
+        .split_whitespace()
+        .map(|word| alphabet.encode_cipher(word))
+        .collect::<Option<Vec<_>>>()
+        .ok_or("Ciphertext has letters that are not in the alphabet")?;
+
+    // Without spaces, the crib and ciphertext are both one long word
+    let (cipher_words, crib_words) = if ignore_spaces {
+        (
+            cipher_words
+                .into_iter()
+                .flatten()
+                .map(|c| vec![c])
+                .collect(),
+            crib_words.concat().into_iter().map(|p| vec![p]).collect(),
+        )
+    } else {
+        (cipher_words, crib_words)
+    };
+    if crib_words.len() > cipher_words.len() {
+        return Ok(Vec::new());
+    }
+
+    Ok((0..=cipher_words.len() - crib_words.len())
+        .filter_map(|position| {
+            let mut key = key;
+            let fits = cipher_words[position..]
+                .iter()
+                .zip(&crib_words)
+                .all(|(cipher, plain)| cipher.len() == plain.len() && key.extend(cipher, plain));
+
+            fits.then(|| Placement {
+                position,
+                ignore_spaces,
+                key: key.to_map(alphabet),
+            })
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crib_placements() {
+        let alphabet = Alphabet::default();
+        let no_key = HashMap::new();
+
+        // Only words with the same pattern fit, and the key must agree with the starting key
+        let placements = place_crib("abc xyz abb", "the", &alphabet, &no_key, 1, false).unwrap();
+        assert_eq!(placements.len(), 2);
+        let placements = place_crib(
+            "abc xyz abb",
+            "the",
+            &alphabet,
+            &[('x', 'a')].into(),
+            1,
+            false,
+        )
+        .unwrap();
+        assert_eq!(placements.len(), 1);
+        assert_eq!(placements[0].position, 0);
+        assert_eq!(placements[0].key.len(), 4);
+
+        let placements = place_crib("abc xyz", "a few", &alphabet, &no_key, 1, false).unwrap();
+        assert!(placements.is_empty());
+
+        // Without spaces, the crib can start at any letter
+        let placements = place_crib("xcb ttloap", "oo", &alphabet, &no_key, 1, true).unwrap();
+        assert_eq!(placements.len(), 1);
+        assert_eq!(placements[0].to_string(), "letter 4");
+
+        assert!(place_crib("abc", "", &alphabet, &no_key, 1, false).is_err());
+    }
+}
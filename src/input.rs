@@ -1,29 +1,17 @@
-use std::collections::{HashMap, HashSet};
+use std::{collections::HashMap, fmt::Debug};
 
-use unidecode::unidecode;
-
-use crate::{normalize, Word};
-
-const ALPHABET: &str = "abcdefghijklmnopqrstuvwxyz";
+use crate::{alphabet::Alphabet, key::Exclusions, normalize, PatternLookup, Word};
 
 /// Clean the input string into a consistent format
-/// - Remove all non-alphabetic characters (only keep spaces)
+/// - Remove all characters that are not in the alphabet (only keep spaces)
 /// - Convert all characters to lowercase
 /// - Trim leading and trailing whitespace
 /// - Remove duplicate spaces
-/// - Normalize unicode characters
-pub fn clean_input(input: &str) -> String {
-    unidecode(input)
+/// - Normalize unicode characters that are not in the alphabet
+pub fn clean_input(input: &str, alphabet: &Alphabet) -> String {
+    input
         .chars()
-        .map(|c| {
-            if c.is_ascii_alphabetic() {
-                c.to_ascii_lowercase()
-            } else {
-                ' '
-            }
-        })
-        // .filter(|c| c.is_ascii_alphabetic() || c == &' ')
-        // .map(|c| c.to_ascii_lowercase())
+        .map(|c| alphabet.fold(c).unwrap_or_else(|| " ".to_string()))
         .collect::<String>()
         .split_whitespace()
         .collect::<Vec<&str>>()
@@ -31,39 +19,151 @@ pub fn clean_input(input: &str) -> String {
 }
 
 /// Parse the input string into a vector of `Word`s.
-/// Returns `None` if the input string contains a word that is not possible in the dictionary
+/// Words that are not possible in the dictionary are kept as unknown words without candidates.
+/// Returns an error if there are more than `max_unknown` different ones of these.
+///
+/// If `max_homophones` is more than 1, different cipher letters may be the same plaintext letter,
+/// so candidates are all words that only need to repeat the letters that the cipher word repeats
 pub fn input_to_words(
     input: &str,
-    dictionary: &HashMap<String, HashSet<String>>,
+    dictionary: &(impl PatternLookup + ?Sized),
+    max_unknown: usize,
+    max_homophones: usize,
 ) -> Result<Vec<Word>, String> {
-    let mut result = Vec::new();
+    let mut result: Vec<Word> = Vec::new();
+    let mut unknown = 0;
 
     for word in input.split_whitespace() {
-        if let Some(candidates) = dictionary.get(&normalize(word)) {
-            result.push(Word::new(word, candidates));
+        let candidates = if max_homophones > 1 {
+            Some(homophonic_candidates(word, dictionary)).filter(|c| !c.is_empty())
         } else {
-            return Err(format!("Word {word:?} is not possible in the dictionary"));
+            dictionary.words(&normalize(word))
+        };
+
+        if let Some(candidates) = candidates {
+            result.push(Word::new(word, &candidates));
+        } else {
+            // A repeated word is skipped only once
+            if !result.iter().any(|w| w.is_unknown() && w.word == word) {
+                unknown += 1;
+            }
+            if unknown > max_unknown {
+                return Err(format!(
+                    "Word \"{word}\" is not possible in the dictionary (allowed unknown words: {max_unknown})"
+                ));
+            }
+            result.push(Word::new(word, &[]));
         }
     }
 
     Ok(result)
 }
 
-pub fn parse_key(key: &str) -> Result<HashMap<char, char>, String> {
+/// All words with a pattern that a homophonic cipher can turn into the pattern of the cipher word.
+/// Repeated cipher letters are still repeated plaintext letters, but repeated plaintext letters can be
+/// different cipher letters (example: "abcd" can be "that", but "abca" can't be "this")
+fn homophonic_candidates<'a>(
+    word: &str,
+    dictionary: &'a (impl PatternLookup + ?Sized),
+) -> Vec<(&'a str, u64)> {
+    let pattern = normalize(word);
+
+    dictionary
+        .patterns()
+        .filter(|(plain_pattern, _)| {
+            let mut map = HashMap::new();
+            plain_pattern.len() == pattern.len()
+                && pattern
+                    .chars()
+                    .zip(plain_pattern.chars())
+                    .all(|(c, p)| *map.entry(c).or_insert(p) == p)
+        })
+        .flat_map(|(_, words)| words)
+        .collect()
+}
+
+/// Parse a starting key, which may also exclude letters: "a!e" means 'a' is not 'e', and "!z" means there is
+/// no 'z' in the plaintext (example: "a:b,c!e,!z"). Up to `max_homophones` cipher letters may map to the same letter
+pub fn parse_key(
+    key: &str,
+    alphabet: &Alphabet,
+    max_homophones: usize,
+) -> Result<(HashMap<char, char>, Exclusions), String> {
+    let (key, exclusions) = split_exclusions(key, alphabet, |a| {
+        let mut chars = a.chars();
+        match (chars.next(), chars.next()) {
+            (Some(a), None) if alphabet.contains(a) => Ok(a),
+            _ => Err(format!(
+                "Invalid key character: {a:?} (should be in lowercase alphabet)"
+            )),
+        }
+    })?;
+    let map = if key.is_empty() {
+        HashMap::new()
+    } else {
+        parse_mapping(&key, alphabet, max_homophones)?
+    };
+
+    if let Some((a, b)) = map.iter().find(|(&a, &b)| exclusions.excludes(a, b)) {
+        return Err(format!("Key maps {a:?} to {b:?}, but also excludes it"));
+    }
+    Ok((map, exclusions))
+}
+
+/// Take the exclusions out of a key, and return the rest of the key.
+/// `cipher_letter` parses the cipher side of an exclusion like "a" in "a!e"
+pub(crate) fn split_exclusions(
+    key: &str,
+    alphabet: &Alphabet,
+    cipher_letter: impl Fn(&str) -> Result<char, String>,
+) -> Result<(String, Exclusions), String> {
+    let mut exclusions = Exclusions::default();
+    let mut rest = Vec::new();
+
+    for pair in key.split(',') {
+        // Mappings can contain a '!' as a symbol, but not without a ':'
+        let Some((cipher, plain)) = pair.rsplit_once('!').filter(|_| !pair.contains(':')) else {
+            rest.push(pair);
+            continue;
+        };
+
+        let mut letters = plain.chars();
+        let plain = match (letters.next(), letters.next()) {
+            (Some(p), None) if alphabet.contains(p) => p,
+            _ => {
+                return Err(format!(
+                    "Invalid key character: {plain:?} (should be in lowercase alphabet)"
+                ))
+            }
+        };
+        if cipher.is_empty() {
+            exclusions.absent.push(plain);
+        } else {
+            exclusions.pairs.push((cipher_letter(cipher)?, plain));
+        }
+    }
+
+    Ok((rest.join(","), exclusions))
+}
+
+/// Parse the letter mappings of a key, in wildcard or delimiter format
+fn parse_mapping(
+    key: &str,
+    alphabet: &Alphabet,
+    max_homophones: usize,
+) -> Result<HashMap<char, char>, String> {
     if key.contains('?') {
         // Key is in wildcard format (example: "b?d?f?????????????????????")
         let mut result = HashMap::new();
-        for (a, b) in ALPHABET.chars().zip(key.chars()) {
+        for (&a, b) in alphabet.letters().iter().zip(key.chars()) {
             if b != '?' {
-                if !ALPHABET.contains(b) {
+                if !alphabet.contains(b) {
                     return Err(format!(
                         "Invalid key character: {b:?} (should be in lowercase alphabet)"
                     ));
                 }
-                if let Some((dup_key, value)) = result.iter().find(|(_, v)| **v == b) {
-                    return Err(format!(
-                        "Duplicate mapping of {value:?} to {dup_key:?} and {a:?}"
-                    ));
+                if let Some(dup_key) = homophones_full(&result, b, max_homophones) {
+                    return Err(too_many_homophones(b, dup_key, a, max_homophones));
                 }
                 result.insert(a, b);
             }
@@ -81,11 +181,11 @@ pub fn parse_key(key: &str) -> Result<HashMap<char, char>, String> {
                     .ok_or(format!("No last character in key: {key:?}"))?,
             );
 
-            if !ALPHABET.contains(a) {
+            if !alphabet.contains(a) {
                 return Err(format!(
                     "Invalid key character: {a:?} (should be in lowercase alphabet)"
                 ));
-            } else if !ALPHABET.contains(b) {
+            } else if !alphabet.contains(b) {
                 return Err(format!(
                     "Invalid key character: {b:?} (should be in lowercase alphabet)"
                 ));
@@ -93,10 +193,8 @@ pub fn parse_key(key: &str) -> Result<HashMap<char, char>, String> {
             if result.contains_key(&a) {
                 return Err(format!("Duplicate key character: {a:?}"));
             }
-            if let Some((dup_key, value)) = result.iter().find(|(_, v)| **v == b) {
-                return Err(format!(
-                    "Duplicate mapping of {value:?} to {dup_key:?} and {a:?}"
-                ));
+            if let Some(dup_key) = homophones_full(&result, b, max_homophones) {
+                return Err(too_many_homophones(b, dup_key, a, max_homophones));
             }
             result.insert(a, b);
         }
@@ -104,74 +202,190 @@ pub fn parse_key(key: &str) -> Result<HashMap<char, char>, String> {
     }
 }
 
+/// A cipher letter that already maps to `letter`, if no more cipher letters may map to it
+pub(crate) fn homophones_full(
+    map: &HashMap<char, char>,
+    letter: char,
+    max_homophones: usize,
+) -> Option<char> {
+    let mut same = map.iter().filter(|(_, &b)| b == letter).map(|(&a, _)| a);
+    let first = same.next()?;
+    (same.count() + 1 >= max_homophones).then_some(first)
+}
+
+/// Error for mapping `other` to `letter`, when `first` and others already map to it
+pub(crate) fn too_many_homophones(
+    letter: char,
+    first: impl Debug,
+    other: impl Debug,
+    max_homophones: usize,
+) -> String {
+    if max_homophones > 1 {
+        format!("Too many letters map to {letter:?} (allowed homophones: {max_homophones})")
+    } else {
+        format!("Duplicate mapping of {letter:?} to {first:?} and {other:?}")
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
     fn clean_input_tests() {
-        assert_eq!(clean_input("Hello, world!"), "hello world");
-        assert_eq!(clean_input("Hello, world! 123"), "hello world");
-        assert_eq!(clean_input("  some   spaces   "), "some spaces");
-        assert_eq!(clean_input("Oké Måns"), "oke mans");
-        assert_eq!(clean_input("Æneid"), "aeneid");
-        assert_eq!(clean_input("test\nword"), "test word");
+        let latin = Alphabet::default();
+        assert_eq!(clean_input("Hello, world!", &latin), "hello world");
+        assert_eq!(clean_input("Hello, world! 123", &latin), "hello world");
+        assert_eq!(clean_input("  some   spaces   ", &latin), "some spaces");
+        assert_eq!(clean_input("Oké Måns", &latin), "oke mans");
+        assert_eq!(clean_input("Æneid", &latin), "aeneid");
+        assert_eq!(clean_input("test\nword", &latin), "test word");
         assert_eq!(
-            clean_input("something.\n\nnow other."),
+            clean_input("something.\n\nnow other.", &latin),
             "something now other"
         );
+
+        let russian = Alphabet::preset("russian").unwrap();
+        assert_eq!(clean_input("Привет, мир! Hello", &russian), "привет мир");
+        let greek = Alphabet::preset("greek").unwrap();
+        assert_eq!(clean_input("Καλημέρα κόσμε.", &greek), "καλημερα κοσμε");
+    }
+
+    #[test]
+    fn input_to_words_unknown() {
+        let dictionary = crate::load_wordlist("a\nfew\nwords", &Alphabet::default());
+
+        assert_eq!(
+            input_to_words("x cbt qwerty", &dictionary, 0, 1).unwrap_err(),
+            "Word \"qwerty\" is not possible in the dictionary (allowed unknown words: 0)"
+        );
+
+        let words = input_to_words("x cbt qwerty", &dictionary, 1, 1).unwrap();
+        assert_eq!(words.len(), 3);
+        assert!(!words[1].is_unknown());
+        assert!(words[2].is_unknown());
+
+        // Repeated, but only one different unknown word
+        let words = input_to_words("x qzqz cbt qzqz", &dictionary, 1, 1).unwrap();
+        assert_eq!(crate::count_unknown(&words), 1);
+        assert!(input_to_words("x qzqz cbt qwerty", &dictionary, 1, 1).is_err());
+    }
+
+    #[test]
+    fn input_to_words_homophonic() {
+        let dictionary = crate::load_wordlist("that\nthis\nword", &Alphabet::default());
+
+        let mut words = input_to_words("abcd", &dictionary, 0, 1).unwrap();
+        words[0].candidates.sort();
+        assert_eq!(words[0].candidates, vec!["this", "word"]);
+
+        // Both 't's in "that" can be different cipher letters, but a repeated cipher letter must be repeated
+        let mut words = input_to_words("abcd abca", &dictionary, 0, 2).unwrap();
+        words[0].candidates.sort();
+        assert_eq!(words[0].candidates, vec!["that", "this", "word"]);
+        assert_eq!(words[1].candidates, vec!["that"]);
     }
 
     #[test]
     fn parse_key_tests() {
+        let latin = Alphabet::default();
         assert_eq!(
-            parse_key("a:b,c:d,e:f").unwrap(),
+            parse_key("a:b,c:d,e:f", &latin, 1).unwrap().0,
             [('a', 'b'), ('c', 'd'), ('e', 'f')]
                 .iter()
                 .cloned()
                 .collect()
         );
         assert_eq!(
-            parse_key("ab,cd,ef").unwrap(),
+            parse_key("ab,cd,ef", &latin, 1).unwrap().0,
             [('a', 'b'), ('c', 'd'), ('e', 'f')]
                 .iter()
                 .cloned()
                 .collect()
         );
         assert_eq!(
-            parse_key("b?d?f?????????????????????????").unwrap(),
+            parse_key("b?d?f?????????????????????????", &latin, 1)
+                .unwrap()
+                .0,
             [('a', 'b'), ('c', 'd'), ('e', 'f')]
                 .iter()
                 .cloned()
                 .collect()
         );
+
+        let russian = Alphabet::preset("russian").unwrap();
+        assert_eq!(
+            parse_key("б?г??????????????????????????????", &russian, 1)
+                .unwrap()
+                .0,
+            [('а', 'б'), ('в', 'г')].into()
+        );
+        assert!(parse_key("b?d??????????????????????????????", &russian, 1).is_err());
     }
 
     #[test]
     fn parse_key_errors() {
+        let latin = Alphabet::default();
         assert_eq!(
-            parse_key("????????A???????b???????c?????").unwrap_err(),
+            parse_key("????????A???????b???????c?????", &latin, 1).unwrap_err(),
             "Invalid key character: 'A' (should be in lowercase alphabet)"
         );
         assert_eq!(
-            parse_key("a???a??????b???c??????????????").unwrap_err(),
+            parse_key("a???a??????b???c??????????????", &latin, 1).unwrap_err(),
             "Duplicate mapping of 'a' to 'a' and 'e'"
         );
         assert_eq!(
-            parse_key("A:b,c:d,e:f").unwrap_err(),
+            parse_key("A:b,c:d,e:f", &latin, 1).unwrap_err(),
             "Invalid key character: 'A' (should be in lowercase alphabet)"
         );
         assert_eq!(
-            parse_key("a:B,c:d,e:f").unwrap_err(),
+            parse_key("a:B,c:d,e:f", &latin, 1).unwrap_err(),
             "Invalid key character: 'B' (should be in lowercase alphabet)"
         );
         assert_eq!(
-            parse_key("ab,cd,af").unwrap_err(),
+            parse_key("ab,cd,af", &latin, 1).unwrap_err(),
             "Duplicate key character: 'a'"
         );
         assert_eq!(
-            parse_key("ab,cd,eb").unwrap_err(),
+            parse_key("ab,cd,eb", &latin, 1).unwrap_err(),
             "Duplicate mapping of 'b' to 'a' and 'e'"
         );
     }
+
+    #[test]
+    fn parse_key_homophones() {
+        let latin = Alphabet::default();
+        let (map, _) = parse_key("b:e,c:e,d:t", &latin, 2).unwrap();
+        assert_eq!(map, [('b', 'e'), ('c', 'e'), ('d', 't')].into());
+        let (map, _) = parse_key("?ee?????????????????????????", &latin, 3).unwrap();
+        assert_eq!(map, [('b', 'e'), ('c', 'e')].into());
+
+        assert_eq!(
+            parse_key("b:e,c:e,d:e", &latin, 2).unwrap_err(),
+            "Too many letters map to 'e' (allowed homophones: 2)"
+        );
+        assert_eq!(
+            parse_key("b:e,c:e", &latin, 1).unwrap_err(),
+            "Duplicate mapping of 'e' to 'b' and 'c'"
+        );
+    }
+
+    #[test]
+    fn parse_key_exclusions() {
+        let latin = Alphabet::default();
+        let (map, exclusions) = parse_key("a:b,c!e,!z", &latin, 1).unwrap();
+        assert_eq!(map, [('a', 'b')].into());
+        assert_eq!(exclusions.pairs, vec![('c', 'e')]);
+        assert_eq!(exclusions.absent, vec!['z']);
+
+        let (map, exclusions) = parse_key("b?d???????????????????????,a!c", &latin, 1).unwrap();
+        assert_eq!(map.len(), 2);
+        assert!(exclusions.excludes('a', 'c'));
+
+        assert_eq!(
+            parse_key("a:b,a!b", &latin, 1).unwrap_err(),
+            "Key maps 'a' to 'b', but also excludes it"
+        );
+        assert!(parse_key("a!E", &latin, 1).is_err());
+    }
 }
@@ -0,0 +1,416 @@
+use std::{
+    collections::HashMap,
+    io::{stderr, BufRead, Write},
+    sync::{mpsc, Arc},
+    thread,
+    time::Duration,
+};
+
+use crate::{
+    alphabet::Alphabet,
+    budget::{Budget, Stop},
+    count_unknown,
+    input::parse_key,
+    key::{Exclusions, Key},
+    loading::Loading,
+    output::Printer,
+    solve::{prune, Solution, Solver},
+    Word,
+};
+
+const HELP: &str = "Commands:
+  a:e, ae, a!e, !z   Pin or forbid letters, in any format of --key (example: \"a:e,b!t\")
+  -ab                Remove the pins and forbidden letters of cipher letters 'a' and 'b'
+  solve [N]          Search with the current key, and print the first N solutions (default: 10)
+  undo               Go back to the key before the last change
+  reset              Start over with an empty key
+  help               Show this help
+  quit               Stop the session";
+
+/// Most solutions that `solve` counts, so it doesn't go through the whole search of a long ciphertext without a key
+const MAX_COUNTED: usize = 1000;
+
+/// Interactive solving session, where letters are pinned or forbidden one step at a time.
+/// After every change the candidates of all words are pruned again, to show how many are left
+pub struct Session {
+    /// Cleaned ciphertext
+    pub ciphertext: String,
+    pub alphabet: Alphabet,
+    /// Maximum number of words that may be skipped as unknown
+    pub max_unknown: usize,
+    /// Most cipher letters that may map to the same plaintext letter
+    pub max_homophones: usize,
+    /// Limits of every search, like `--max-solutions`, `--timeout` and `--max-nodes`
+    pub max_solutions: Option<usize>,
+    pub timeout: Option<Duration>,
+    pub max_nodes: Option<usize>,
+    /// Cipher words with all of their candidates from the dictionary
+    words: Vec<Word>,
+    /// Pinned letters
+    key: HashMap<char, char>,
+    exclusions: Exclusions,
+    /// Keys before every change, to undo them
+    history: Vec<(HashMap<char, char>, Exclusions)>,
+}
+impl Session {
+    pub fn new(ciphertext: &str, words: Vec<Word>) -> Self {
+        Session {
+            ciphertext: ciphertext.to_string(),
+            alphabet: Alphabet::default(),
+            max_unknown: 0,
+            max_homophones: 1,
+            max_solutions: None,
+            timeout: None,
+            max_nodes: None,
+            words,
+            key: HashMap::new(),
+            exclusions: Exclusions::default(),
+            history: Vec::new(),
+        }
+    }
+
+    /// Start with a key, like the one from `--key`
+    pub fn set_key(&mut self, key: HashMap<char, char>, exclusions: Exclusions) {
+        self.key = key;
+        self.exclusions = exclusions;
+    }
+
+    /// Pin and forbid the letters of a key (example: "a:e,b!t")
+    pub fn pin(&mut self, key: &str) -> Result<(), String> {
+        let (map, exclusions) = parse_key(key, &self.alphabet, self.max_homophones)?;
+
+        let mut new_key = self.key.clone();
+        new_key.extend(map);
+        let mut new_exclusions = self.exclusions.clone();
+        new_exclusions.pairs.extend(exclusions.pairs);
+        new_exclusions.absent.extend(exclusions.absent);
+
+        if let Some((a, b)) = new_key
+            .iter()
+            .find(|(&a, &b)| new_exclusions.excludes(a, b))
+        {
+            return Err(format!("Key maps {a:?} to {b:?}, but also excludes it"));
+        }
+        if Key::from_map(&new_key, &self.alphabet, self.max_homophones).is_none() {
+            return Err("Key maps too many letters to the same letter".to_string());
+        }
+
+        self.history
+            .push((self.key.clone(), self.exclusions.clone()));
+        self.key = new_key;
+        self.exclusions = new_exclusions;
+        Ok(())
+    }
+
+    /// Remove the pins and forbidden letters of these cipher letters
+    pub fn unpin(&mut self, letters: &str) {
+        self.history
+            .push((self.key.clone(), self.exclusions.clone()));
+        self.key.retain(|a, _| !letters.contains(*a));
+        self.exclusions.pairs.retain(|(a, _)| !letters.contains(*a));
+    }
+
+    /// Go back to the key before the last change. Returns `false` if there is nothing to undo
+    pub fn undo(&mut self) -> bool {
+        match self.history.pop() {
+            Some((key, exclusions)) => {
+                (self.key, self.exclusions) = (key, exclusions);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.history
+            .push((self.key.clone(), self.exclusions.clone()));
+        self.key.clear();
+        self.exclusions = Exclusions::default();
+    }
+
+    /// Cipher words with only the candidates that still fit the current key
+    pub fn candidates(&self) -> Vec<Word> {
+        let Some(key) = Key::from_map(&self.key, &self.alphabet, self.max_homophones) else {
+            return Vec::new();
+        };
+
+        let mut words = self.words.clone();
+        for word in words.iter_mut() {
+            let Some(cipher) = self.alphabet.encode_cipher(&word.word) else {
+                continue;
+            };
+            word.candidates.retain(|candidate| {
+                self.alphabet
+                    .encode(candidate)
+                    .is_some_and(|plain| key.fits(&cipher, &plain))
+            });
+        }
+
+        let unknown = count_unknown(&words);
+        if unknown < self.max_unknown {
+            // Skipped words can't rule out letters for each other, like when pruning normally
+            words
+        } else if unknown > self.max_unknown {
+            // A word that had candidates has none left
+            words.iter_mut().for_each(|w| w.candidates.clear());
+            words
+        } else {
+            prune(
+                &mut words,
+                &self.alphabet,
+                self.max_homophones,
+                &self.exclusions,
+            );
+            words
+        }
+    }
+
+    /// Search with the current key, and send the solutions until a limit is reached.
+    /// At most `MAX_COUNTED` solutions are searched for. Returns the limit that stopped the search
+    pub fn solve(&self, tx: &mpsc::Sender<Solution>) -> Option<Stop> {
+        let max_solutions = self
+            .max_solutions
+            .map_or(MAX_COUNTED, |max| max.min(MAX_COUNTED));
+        let budget = Arc::new(Budget::new(
+            Some(max_solutions),
+            self.timeout,
+            self.max_nodes,
+        ));
+
+        let mut solver = Solver::new(&self.candidates());
+        solver.alphabet = self.alphabet.clone();
+        solver.max_unknown = self.max_unknown;
+        solver.max_homophones = self.max_homophones;
+        solver.exclusions = self.exclusions.clone();
+        solver.budget = budget.clone();
+        solver.solve(self.key.clone(), Some(tx));
+        budget.stopped()
+    }
+
+    /// Ciphertext, plaintext with the current key and the number of candidates of every word.
+    /// Single and missing candidates are colored if `terminal` is set
+    pub fn render(&self, terminal: bool) -> String {
+        let mut solution = Solution::new(self.key.clone());
+        solution.alphabet = self.alphabet.clone();
+
+        let mut result = format!(
+            "Key:        {solution}\nCiphertext: {}\nPlaintext:  {}\n",
+            self.ciphertext,
+            solution.apply(&self.ciphertext)
+        );
+
+        let mut shown = Vec::new();
+        for word in self.candidates() {
+            if shown.contains(&word.word) {
+                continue;
+            }
+            let count = match word.candidates.as_slice() {
+                [only] if terminal => format!("\x1B[92m{only}\x1B[0m"),
+                [] if terminal => "\x1B[91m0\x1B[0m".to_string(),
+                [only] => only.clone(),
+                candidates => candidates.len().to_string(),
+            };
+            result += &format!("  {} ({count})\n", word.word);
+            shown.push(word.word);
+        }
+
+        let forbidden = (self.exclusions.pairs.iter())
+            .map(|(a, b)| format!("{a}!{b}"))
+            .chain(self.exclusions.absent.iter().map(|b| format!("!{b}")))
+            .collect::<Vec<_>>();
+        if !forbidden.is_empty() {
+            result += &format!("Forbidden:  {}\n", forbidden.join(","));
+        }
+        result
+    }
+}
+
+/// Read commands from `input` until it ends or the user quits, showing the session after every change
+pub fn run(
+    loading: &Loading,
+    session: &mut Session,
+    printer: &mut Printer,
+    input: impl BufRead,
+) -> std::io::Result<()> {
+    loading.end();
+    draw(loading, session)?;
+    loading.info("Type \"help\" for a list of commands".to_string());
+    prompt(loading)?;
+
+    for line in input.lines() {
+        let line = line?;
+        let line = line.trim();
+        let (command, argument) = line.split_once(' ').unwrap_or((line, ""));
+
+        match command {
+            "" => {}
+            "quit" | "exit" | "q" => break,
+            "help" | "?" => eprintln!("{HELP}"),
+            "solve" => match argument {
+                "" => solve(loading, session, printer, 10),
+                count => match count.parse() {
+                    Ok(count) => solve(loading, session, printer, count),
+                    Err(_) => loading.fail(format!("Invalid number of solutions: {count:?}")),
+                },
+            },
+            "undo" => {
+                let undone = session.undo();
+                draw(loading, session)?;
+                if !undone {
+                    loading.warn("Nothing to undo".to_string());
+                }
+            }
+            "reset" => {
+                session.reset();
+                draw(loading, session)?;
+            }
+            _ => {
+                let result = match command.strip_prefix('-') {
+                    Some(letters) => {
+                        session.unpin(letters);
+                        Ok(())
+                    }
+                    None => session.pin(line),
+                };
+                draw(loading, session)?;
+                if let Err(e) = result {
+                    loading.fail(e);
+                }
+            }
+        }
+
+        prompt(loading)?;
+    }
+
+    Ok(())
+}
+
+/// Print the first `count` solutions with the current key, and how many there are in total
+fn solve(loading: &Loading, session: &Session, printer: &mut Printer, count: usize) {
+    let (tx, rx) = mpsc::channel();
+    let (solutions, stopped) = thread::scope(|s| {
+        let search = s.spawn(move || session.solve(&tx));
+
+        let mut solutions = 0;
+        for mut solution in rx {
+            if solutions < count {
+                let plaintext;
+                (plaintext, solution.unknown_positions) =
+                    solution.apply_with_unknown(&session.ciphertext);
+                printer.print(&solution, &plaintext);
+            }
+            solutions += 1;
+        }
+        (solutions, search.join().unwrap())
+    });
+
+    match (solutions, stopped) {
+        (0, None) => loading.fail("No solutions with this key".to_string()),
+        (0, Some(stop)) => loading.warn(format!(
+            "No solutions found before reaching the {stop} limit"
+        )),
+        (_, None) => loading.success(format!(
+            "Found {solutions} solutions (showing {})",
+            solutions.min(count)
+        )),
+        (_, Some(stop)) => loading.warn(format!(
+            "Found at least {solutions} solutions (showing {}, stopped by the {stop} limit)",
+            solutions.min(count)
+        )),
+    }
+}
+
+/// Clear the terminal, and show the session. Without a terminal, it is written after the previous output
+fn draw(loading: &Loading, session: &Session) -> std::io::Result<()> {
+    let mut output = stderr();
+    if loading.is_terminal() {
+        write!(output, "\x1B[2J\x1B[H{}", session.render(true))?;
+    } else {
+        write!(output, "{}", session.render(false))?;
+    }
+    output.flush()
+}
+
+/// Wait until all status messages are shown, and ask for the next command
+fn prompt(loading: &Loading) -> std::io::Result<()> {
+    loading.end();
+    let mut output = stderr();
+    write!(output, "> ")?;
+    output.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{input::input_to_words, load_wordlist};
+
+    use super::*;
+
+    #[test]
+    fn pin_and_forbid() {
+        let dictionary = load_wordlist("tea\nten\nthe\nfor\nfew", &Alphabet::default());
+        let words = input_to_words("abc abd", &dictionary, 0, 1).unwrap();
+        let mut session = Session::new("abc abd", words);
+
+        let counts = |session: &Session| {
+            (session.candidates().iter())
+                .map(|w| w.candidates.len())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(counts(&session), vec![5, 5]);
+
+        session.pin("a:t,b:e").unwrap();
+        assert_eq!(counts(&session), vec![2, 2]);
+        session.pin("c!a").unwrap();
+        assert_eq!(session.candidates()[0].candidates, vec!["ten"]);
+        assert_eq!(session.candidates()[1].candidates, vec!["tea"]);
+        assert!(session.pin("a:t,d:t").is_err());
+        assert!(session.pin("c:a").is_err());
+
+        session.pin("a:f").unwrap();
+        assert_eq!(counts(&session), vec![0, 0]);
+        assert!(session.undo());
+        assert_eq!(counts(&session), vec![1, 1]);
+
+        session.unpin("c");
+        assert_eq!(counts(&session), vec![2, 2]);
+        session.reset();
+        assert_eq!(counts(&session), vec![5, 5]);
+        assert!(session.undo());
+        assert_eq!(counts(&session), vec![2, 2]);
+    }
+
+    #[test]
+    fn solve_within_limits() {
+        let dictionary = load_wordlist("tea\nten\nthe\nfor\nfew", &Alphabet::default());
+        let words = input_to_words("abc", &dictionary, 0, 1).unwrap();
+        let mut session = Session::new("abc", words);
+
+        let solve = |session: &Session| {
+            let (tx, rx) = mpsc::channel();
+            let stopped = session.solve(&tx);
+            drop(tx);
+            (rx.iter().count(), stopped)
+        };
+        assert_eq!(solve(&session), (5, None));
+
+        session.max_solutions = Some(2);
+        assert_eq!(solve(&session), (2, Some(Stop::Solutions)));
+        session.max_solutions = None;
+        session.max_nodes = Some(1);
+        assert_eq!(solve(&session).1, Some(Stop::Nodes));
+    }
+
+    #[test]
+    fn render_plain() {
+        let dictionary = load_wordlist("tea\nten\nthe\nfor\nfew", &Alphabet::default());
+        let words = input_to_words("abc abd xx", &dictionary, 1, 1).unwrap();
+        let mut session = Session::new("abc abd xx", words);
+        session.max_unknown = 1;
+        session.pin("a:t,b:e,c:n").unwrap();
+
+        let plain = session.render(false);
+        assert!(!plain.contains('\x1B'));
+        assert!(plain.contains("  abc (ten)\n  abd (tea)\n  xx (0)\n"));
+        assert!(session.render(true).contains("\x1B[92mten\x1B[0m"));
+    }
+}
@@ -0,0 +1,276 @@
+use std::{
+    collections::HashMap,
+    ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign},
+};
+
+use crate::alphabet::Alphabet;
+
+/// Most letters an alphabet can have, because sets of letters are stored in a `u64`
+pub const MAX_LETTERS: usize = 64;
+/// Marks a letter in a `Key` that is not mapped yet
+const UNKNOWN: u8 = u8::MAX;
+
+/// Set of letters stored as a bitmask, where bit `i` means letter `i` of the alphabet is in the set
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LetterSet(pub u64);
+impl LetterSet {
+    pub const EMPTY: LetterSet = LetterSet(0);
+
+    /// All letters of an alphabet with `len` letters
+    pub fn all(len: usize) -> Self {
+        LetterSet(u64::MAX >> (MAX_LETTERS - len))
+    }
+
+    pub fn single(i: u8) -> Self {
+        LetterSet(1 << i)
+    }
+
+    pub fn contains(&self, i: u8) -> bool {
+        self.0 & (1 << i) != 0
+    }
+
+    pub fn insert(&mut self, i: u8) {
+        self.0 |= 1 << i;
+    }
+
+    pub fn remove(&mut self, i: u8) {
+        self.0 &= !(1 << i);
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.count_ones() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Iterate over the indices of all letters in the set, in alphabetical order
+    pub fn iter(&self) -> impl Iterator<Item = u8> {
+        let bits = self.0;
+        (0..MAX_LETTERS as u8).filter(move |i| bits & (1 << i) != 0)
+    }
+}
+impl BitAnd for LetterSet {
+    type Output = LetterSet;
+
+    fn bitand(self, rhs: Self) -> Self::Output {
+        LetterSet(self.0 & rhs.0)
+    }
+}
+impl BitAndAssign for LetterSet {
+    fn bitand_assign(&mut self, rhs: Self) {
+        self.0 &= rhs.0;
+    }
+}
+impl BitOr for LetterSet {
+    type Output = LetterSet;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        LetterSet(self.0 | rhs.0)
+    }
+}
+impl BitOrAssign for LetterSet {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+/// Mapping from cipher letters to plaintext letters. Normally one-to-one, but homophonic keys can map
+/// multiple cipher letters to the same plaintext letter
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Key {
+    map: [u8; MAX_LETTERS],
+    /// Number of cipher letters that map to each plaintext letter
+    counts: [u8; MAX_LETTERS],
+    /// Most cipher letters that may map to the same plaintext letter
+    max_homophones: u8,
+}
+impl Default for Key {
+    fn default() -> Self {
+        Key::new(1)
+    }
+}
+impl Key {
+    /// Empty key where up to `max_homophones` cipher letters may map to the same plaintext letter (1 is one-to-one)
+    pub fn new(max_homophones: usize) -> Self {
+        Key {
+            map: [UNKNOWN; MAX_LETTERS],
+            counts: [0; MAX_LETTERS],
+            max_homophones: max_homophones.clamp(1, MAX_LETTERS) as u8,
+        }
+    }
+
+    /// Convert a letter mapping into a key, or `None` if it maps too many letters to the same letter
+    pub fn from_map(
+        map: &HashMap<char, char>,
+        alphabet: &Alphabet,
+        max_homophones: usize,
+    ) -> Option<Self> {
+        let mut key = Key::new(max_homophones);
+
+        for (&a, &b) in map {
+            if !key.insert(alphabet.cipher_index(a)?, alphabet.index(b)?) {
+                return None;
+            }
+        }
+
+        Some(key)
+    }
+
+    pub fn to_map(&self, alphabet: &Alphabet) -> HashMap<char, char> {
+        (0..alphabet.cipher_letters().len() as u8)
+            .filter_map(|c| {
+                self.get(c)
+                    .map(|p| (alphabet.cipher_letter(c), alphabet.letter(p)))
+            })
+            .collect()
+    }
+
+    /// Plaintext letter of a cipher letter, if it is known
+    pub fn get(&self, c: u8) -> Option<u8> {
+        match self.map[c as usize] {
+            UNKNOWN => None,
+            p => Some(p),
+        }
+    }
+
+    /// Check if no more cipher letters can map to a plaintext letter
+    pub fn is_full(&self, p: u8) -> bool {
+        self.counts[p as usize] >= self.max_homophones
+    }
+
+    /// Map a cipher letter to a plaintext letter. Returns `false` if the cipher letter is already mapped to
+    /// something else, or if the plaintext letter already has the maximum number of cipher letters
+    pub fn insert(&mut self, c: u8, p: u8) -> bool {
+        match self.map[c as usize] {
+            UNKNOWN if !self.is_full(p) => {
+                self.map[c as usize] = p;
+                self.counts[p as usize] += 1;
+                true
+            }
+            existing => existing == p,
+        }
+    }
+
+    /// Check if a cipher word can map to a plaintext word, without changing the key
+    pub fn fits(&self, cipher: &[u8], plain: &[u8]) -> bool {
+        let mut copy = *self;
+        copy.extend(cipher, plain)
+    }
+
+    /// Add all letters of a cipher word mapping to a plaintext word.
+    /// Returns `false` if the words don't fit the key, in which case the key may be partially updated
+    pub fn extend(&mut self, cipher: &[u8], plain: &[u8]) -> bool {
+        cipher.iter().zip(plain).all(|(&c, &p)| self.insert(c, p))
+    }
+
+    /// Number of mapped letters
+    pub fn len(&self) -> usize {
+        self.map.iter().filter(|&&p| p != UNKNOWN).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Plaintext letters that cipher letters are known not to be, from keys like "a!e" and "!z"
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Exclusions {
+    /// Cipher letter with a plaintext letter that it can't be
+    pub pairs: Vec<(char, char)>,
+    /// Plaintext letters that are not in the plaintext at all
+    pub absent: Vec<char>,
+}
+impl Exclusions {
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty() && self.absent.is_empty()
+    }
+
+    /// Possible plaintext letters for every cipher letter
+    pub fn allowed(&self, alphabet: &Alphabet) -> [LetterSet; MAX_LETTERS] {
+        let mut all = LetterSet::all(alphabet.len());
+        for p in self.absent.iter().filter_map(|&p| alphabet.index(p)) {
+            all.remove(p);
+        }
+
+        let mut allowed = [all; MAX_LETTERS];
+        for &(c, p) in &self.pairs {
+            if let (Some(c), Some(p)) = (alphabet.cipher_index(c), alphabet.index(p)) {
+                allowed[c as usize].remove(p);
+            }
+        }
+        allowed
+    }
+
+    /// Check if a cipher letter is known not to be a plaintext letter
+    pub fn excludes(&self, c: char, p: char) -> bool {
+        self.absent.contains(&p) || self.pairs.contains(&(c, p))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn letter_set() {
+        let mut set = LetterSet::single(2);
+        set.insert(0);
+        set.insert(63);
+        assert_eq!(set.len(), 3);
+        assert_eq!(set.iter().collect::<Vec<_>>(), vec![0, 2, 63]);
+
+        set.remove(2);
+        assert!(!set.contains(2));
+        assert_eq!(set & LetterSet::single(0), LetterSet::single(0));
+        assert_eq!(LetterSet::all(26).len(), 26);
+        assert_eq!(LetterSet::all(MAX_LETTERS).len(), MAX_LETTERS);
+    }
+
+    #[test]
+    fn key_extend() {
+        let alphabet = Alphabet::default();
+        let encode = |word| alphabet.encode(word).unwrap();
+        let mut key = Key::default();
+        assert!(key.extend(&encode("cbt"), &encode("few")));
+        assert!(key.extend(&encode("tlo"), &encode("wor")));
+        assert_eq!(key.get(alphabet.index('t').unwrap()), alphabet.index('w'));
+        assert_eq!(key.len(), 5);
+
+        // 'x' can't also map to 'f'
+        let mut copy = key;
+        assert!(!copy.extend(&encode("x"), &encode("f")));
+        // 'c' can't map to something other than 'f'
+        let mut copy = key;
+        assert!(!copy.extend(&encode("c"), &encode("a")));
+
+        assert!(key.fits(&encode("tx"), &encode("wa")));
+        assert!(!key.fits(&encode("tx"), &encode("we")));
+
+        assert_eq!(
+            Key::from_map(&key.to_map(&alphabet), &alphabet, 1),
+            Some(key)
+        );
+        assert_eq!(
+            Key::from_map(&[('a', 'b'), ('c', 'b')].into(), &alphabet, 1),
+            None
+        );
+    }
+
+    #[test]
+    fn homophonic_key() {
+        let alphabet = Alphabet::default();
+        let encode = |word| alphabet.encode(word).unwrap();
+        let mut key = Key::new(2);
+
+        // 'x' and 'y' can both be 'e', but 'z' can't be a third one
+        assert!(key.extend(&encode("xy"), &encode("ee")));
+        assert!(key.is_full(alphabet.index('e').unwrap()));
+        assert!(!key.fits(&encode("z"), &encode("e")));
+        assert!(key.fits(&encode("zy"), &encode("te")));
+        assert!(key.fits(&encode("ab"), &encode("tt")));
+        assert!(!key.fits(&encode("abc"), &encode("ttt")));
+    }
+}
@@ -1,38 +1,119 @@
-#[macro_use]
-extern crate lazy_static;
-
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{HashMap, HashSet},
+    io::{self, BufRead},
+};
 
+use alphabet::Alphabet;
 use input::clean_input;
 
+pub mod alphabet;
+pub mod budget;
 pub mod cache;
 pub mod cli;
+pub mod climb;
+pub mod crib;
 pub mod input;
+pub mod interactive;
+pub mod key;
 pub mod loading;
+pub mod mapped;
+pub mod output;
+pub mod score;
+pub mod segment;
 pub mod solve;
+pub mod symbols;
+pub mod wordlist;
+
+/// Normalized patterns mapped to all words with that pattern, and how often each word occurs
+pub type Dictionary = HashMap<String, HashMap<String, u64>>;
+
+/// Every pattern with its words and how often they occur, from `PatternLookup::patterns`
+pub type Patterns<'a> = Box<dyn Iterator<Item = (&'a str, Vec<(&'a str, u64)>)> + 'a>;
+
+/// Words grouped by their normalized pattern, like a `Dictionary` in memory or a `MappedDictionary` in a file
+pub trait PatternLookup: Sync {
+    /// Words with this pattern and how often each of them occurs, or `None` if no word has it
+    fn words(&self, pattern: &str) -> Option<Vec<(&str, u64)>>;
+
+    /// Every pattern with its words
+    fn patterns(&self) -> Patterns<'_>;
+
+    /// Number of patterns
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+impl PatternLookup for Dictionary {
+    fn words(&self, pattern: &str) -> Option<Vec<(&str, u64)>> {
+        self.get(pattern).map(word_counts)
+    }
+
+    fn patterns(&self) -> Patterns<'_> {
+        Box::new(
+            self.iter()
+                .map(|(pattern, words)| (pattern.as_str(), word_counts(words))),
+        )
+    }
+
+    fn len(&self) -> usize {
+        HashMap::len(self)
+    }
+}
+
+fn word_counts(words: &HashMap<String, u64>) -> Vec<(&str, u64)> {
+    words
+        .iter()
+        .map(|(word, &count)| (word.as_str(), count))
+        .collect()
+}
+
+/// Built-in english wordlist, used when no other wordlist is given
+pub const BUILTIN_WORDLIST: &str = include_str!("../wordlist/english.txt");
+
+/// Dictionary of the built-in wordlist with the default alphabet, which was already built when compiling
+pub fn builtin_dictionary() -> Dictionary {
+    bincode::deserialize(include_bytes!(concat!(env!("OUT_DIR"), "/english.bin")))
+        .expect("built-in dictionary should be valid")
+}
 
 #[derive(Debug, Clone)]
 pub struct Word {
     pub word: String,
-    pub candidates: HashSet<String>,
-    pub letter_map: HashMap<char, HashSet<char>>,
+    /// Possible plaintext words, most frequent first
+    pub candidates: Vec<String>,
 }
 impl Word {
-    pub fn new(s: &str, candidates: &HashSet<String>) -> Self {
-        let mut letter_map = HashMap::new();
-
-        for word in candidates {
-            for (i, j) in s.chars().zip(word.chars()) {
-                letter_map.entry(i).or_insert(HashSet::new()).insert(j);
-            }
-        }
+    /// Cipher word with its candidates and how often they occur
+    pub fn new(s: &str, candidates: &[(&str, u64)]) -> Self {
+        let mut sorted = candidates.to_vec();
+        sorted.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(a.cmp(b)));
+        let candidates = sorted
+            .into_iter()
+            .map(|(word, _)| word.to_string())
+            .collect::<Vec<_>>();
 
         Word {
             word: s.to_string(),
-            candidates: candidates.clone(),
-            letter_map,
+            candidates,
         }
     }
+
+    /// A word is unknown if no word in the dictionary fits its pattern
+    pub fn is_unknown(&self) -> bool {
+        self.candidates.is_empty()
+    }
+}
+
+/// Number of different unknown cipher words. A repeated word always has the same plaintext, so it is only skipped once
+pub fn count_unknown(words: &[Word]) -> usize {
+    words
+        .iter()
+        .filter(|w| w.is_unknown())
+        .map(|w| &w.word)
+        .collect::<HashSet<_>>()
+        .len()
 }
 
 /// Convert word to uppercase, and substitute all characters to be in alphabetical order.
@@ -71,16 +152,90 @@ pub fn normalize(s: &str) -> String {
     result.into_iter().collect()
 }
 
-/// Load a wordlist from a file into a dictionary with normalized words
-pub fn load_wordlist(contents: &str) -> HashMap<String, HashSet<String>> {
-    let mut map = HashMap::new();
+/// Load a wordlist from a file into a dictionary with normalized words, cleaned into letters of the alphabet.
+/// Lines can optionally contain a count after the word, separated by a tab or space (example: "the\t1000")
+///
+/// ```rust
+/// use sub_solver::{alphabet::Alphabet, load_wordlist};
+///
+/// let dictionary = load_wordlist("the\t1000\nthy 3\nand", &Alphabet::default());
+/// assert_eq!(dictionary["ABC"]["the"], 1000);
+/// assert_eq!(dictionary["ABC"]["thy"], 3);
+/// assert_eq!(dictionary["ABC"]["and"], 1);
+/// ```
+pub fn load_wordlist(contents: &str, alphabet: &Alphabet) -> Dictionary {
+    read_wordlist(contents.as_bytes(), alphabet, |_| true).expect("reading from memory can't fail")
+}
 
-    for word in contents.lines() {
-        let word = clean_input(word);
-        map.entry(normalize(&word))
-            .or_insert(HashSet::new())
-            .insert(word.to_string());
+/// Same as `load_wordlist`, but reading one line at a time so the whole file is never in memory.
+/// Only words with a pattern that `keep` returns true for are added to the dictionary
+///
+/// ```rust
+/// use sub_solver::{alphabet::Alphabet, read_wordlist};
+///
+/// let wordlist = "the\t1000\nthy 3\nand\nthat".as_bytes();
+/// let dictionary = read_wordlist(wordlist, &Alphabet::default(), |pattern| pattern == "ABCA").unwrap();
+/// assert_eq!(dictionary.len(), 1);
+/// assert_eq!(dictionary["ABCA"]["that"], 1);
+/// ```
+pub fn read_wordlist(
+    reader: impl BufRead,
+    alphabet: &Alphabet,
+    keep: impl Fn(&str) -> bool,
+) -> io::Result<Dictionary> {
+    let mut map = Dictionary::new();
+
+    for word in read_words(reader, alphabet) {
+        let (word, count) = word?;
+        let pattern = normalize(&word);
+        if !keep(&pattern) {
+            continue;
+        }
+        *map.entry(pattern).or_default().entry(word).or_default() += count;
     }
 
-    map
+    Ok(map)
+}
+
+/// Words in a wordlist with their counts, cleaned into letters of the alphabet. Lines without letters are skipped
+pub fn read_words<'a>(
+    reader: impl BufRead + 'a,
+    alphabet: &'a Alphabet,
+) -> impl Iterator<Item = io::Result<(String, u64)>> + 'a {
+    reader.lines().filter_map(move |line| {
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => return Some(Err(e)),
+        };
+        let (word, count) = parse_wordlist_line(&line);
+        let word = clean_input(word, alphabet);
+        (!word.is_empty()).then_some(Ok((word, count)))
+    })
+}
+
+/// Split a line into the word and its count, which defaults to 1
+pub(crate) fn parse_wordlist_line(line: &str) -> (&str, u64) {
+    let line = line.trim();
+
+    if let Some((word, count)) = line.rsplit_once(['\t', ' ']) {
+        if let Ok(count) = count.trim().parse() {
+            return (word, count);
+        }
+    }
+
+    (line, 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtin_dictionary_is_up_to_date() {
+        // The build script doesn't use the alphabet, so it must give the same dictionary
+        assert_eq!(
+            builtin_dictionary(),
+            load_wordlist(BUILTIN_WORDLIST, &Alphabet::default())
+        );
+    }
 }
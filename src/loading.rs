@@ -1,6 +1,6 @@
 /// Source: https://github.com/wyhaya/loading/blob/main/src/lib.rs
-/// Formatting altered slightly
-use std::io::{stderr, Write};
+/// Formatting altered slightly, and only plain status lines if stderr is not a terminal
+use std::io::{stderr, IsTerminal, Write};
 use std::sync::mpsc::{self, Receiver, Sender};
 use std::thread;
 use std::time::Duration;
@@ -8,6 +8,8 @@ use std::time::Duration;
 #[derive(Debug)]
 pub struct Loading {
     sender: Sender<Signal>,
+    /// If the spinner and colors can be shown, otherwise status lines are written as plain text
+    terminal: bool,
 }
 
 impl Default for Loading {
@@ -20,11 +22,37 @@ impl Loading {
     /// Create a stdout loading
     pub fn new(spinner: Spinner) -> Self {
         let (sender, receiver) = mpsc::channel();
+        let terminal = stderr().is_terminal();
 
-        Self::update_stdout(receiver);
-        Self::update_animation(sender.clone(), spinner);
+        Self::update_stdout(receiver, terminal);
+        if terminal {
+            Self::update_animation(sender.clone(), spinner);
+        }
+
+        Self { sender, terminal }
+    }
+
+    /// Loading that shows nothing, for work that reports its results in another way
+    pub fn hidden() -> Self {
+        let (sender, receiver) = mpsc::channel();
+
+        thread::spawn(move || {
+            for signal in receiver {
+                if let Signal::Exit(sender) = signal {
+                    let _ = sender.send(());
+                }
+            }
+        });
+
+        Self {
+            sender,
+            terminal: false,
+        }
+    }
 
-        Self { sender }
+    /// If stderr is a terminal, so colors and other escape codes can be written to it
+    pub fn is_terminal(&self) -> bool {
+        self.terminal
     }
 
     /// End loading
@@ -70,7 +98,11 @@ impl Loading {
 
     /// Save the current line as 'debug' and continue to load on the next line
     pub fn debug<T: ToString>(&self, text: T) {
-        let text = format!("\x1B[90m{}\x1B[0m", text.to_string());
+        let text = if self.terminal {
+            format!("\x1B[90m{}\x1B[0m", text.to_string())
+        } else {
+            text.to_string()
+        };
 
         let _ = self.sender.send(Signal::Next(Status::Debug, text));
     }
@@ -83,7 +115,7 @@ impl Loading {
         });
     }
 
-    fn update_stdout(receiver: Receiver<Signal>) {
+    fn update_stdout(receiver: Receiver<Signal>, terminal: bool) {
         thread::spawn(move || {
             let mut output = stderr();
             let mut frame = "";
@@ -101,6 +133,22 @@ impl Loading {
                 };
             }
 
+            if !terminal {
+                // Only status lines, without escape codes that would end up in logs and pipes
+                while let Ok(signal) = receiver.recv() {
+                    match signal {
+                        Signal::Next(status, s) => {
+                            let _ = writeln!(output, "[{}] {}", status.as_plain_str(), s);
+                        }
+                        Signal::Exit(sender) => {
+                            let _ = sender.send(());
+                        }
+                        Signal::Frame(_) | Signal::Text(_) => {}
+                    }
+                }
+                return;
+            }
+
             let mut show_loader = true;
             while let Ok(signal) = receiver.recv() {
                 match signal {
@@ -199,4 +247,14 @@ impl Status {
             Status::Debug => "\x1B[90m \x1B[0m",
         }
     }
+
+    fn as_plain_str(&self) -> &'static str {
+        match self {
+            Status::Success => "+",
+            Status::Fail => "FAIL",
+            Status::Warn => "!",
+            Status::Info => "*",
+            Status::Debug => " ",
+        }
+    }
 }
@@ -1,20 +1,47 @@
 use std::{
-    collections::HashMap, error::Error, fs::read_to_string, io::IsTerminal, sync::mpsc, thread,
+    collections::{HashMap, HashSet},
+    error::Error,
+    fs::{self, read_to_string, File},
+    io::{self, stdin, IsTerminal},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc, Arc, OnceLock,
+    },
+    thread,
+    time::{Duration, Instant},
 };
 
-use clap::Parser;
-
 use sub_solver::{
-    cache::{load_cached_dictionary, save_cached_dictionary},
-    cli::Args,
+    alphabet::Alphabet,
+    budget::{Budget, Stop},
+    builtin_dictionary,
+    cache::{
+        cache_dir, list_entries, load_cached_dictionary, remove_entries, save_cached_dictionary,
+        CacheError, HashingReader, WordlistFile, CACHE_DIR_ENV,
+    },
+    cli::{Args, CacheCommand, Command},
+    climb::HillClimber,
+    count_unknown,
+    crib::place_crib,
     input::{clean_input, input_to_words, parse_key},
+    interactive::{self, Session},
+    key::{Exclusions, MAX_LETTERS},
     load_wordlist,
     loading::Loading,
-    solve::{prune, Solver},
+    normalize,
+    output::Printer,
+    read_wordlist,
+    score::{NgramModel, Scorer},
+    segment::SegmentSolver,
+    solve::{prune, Solution, Solver},
+    symbols::Symbols,
+    wordlist::{decompress, open_wordlist},
+    PatternLookup, BUILTIN_WORDLIST,
 };
 
 fn main() {
-    let args = Args::parse();
+    let args = Args::parse_args();
 
     let loading = Loading::default();
 
@@ -23,125 +50,803 @@ fn main() {
         loading.end();
         std::process::exit(1);
     }
+    loading.end();
 }
 
 fn do_main(loading: &Loading, args: Args) -> Result<(), Box<dyn Error>> {
-    // Parse args
-    let starting_key = match args.key {
-        Some(key) => {
-            loading.info(format!("Using starting key: {key:?}"));
-            parse_key(&key)?
-        }
-        None => {
-            loading.info("Using empty starting key".to_string());
-            HashMap::new()
-        }
+    let start = Instant::now();
+
+    let cache_dir = cache_dir(args.cache_dir.as_deref());
+    if let Some(Command::Cache(command)) = &args.command {
+        let cache_dir = cache_dir.ok_or(format!(
+            "No cache directory found on this system, set one with --cache-dir or {CACHE_DIR_ENV}"
+        ))?;
+        return cache_command(loading, &cache_dir, command);
+    }
+
+    // Read before the wordlist, so only the patterns in it have to be loaded without a cache
+    let ciphertext = match args.batch {
+        true => None,
+        false => Some(read_ciphertext(loading, &args)?),
     };
 
     loading.text("Loading wordlist...".to_string());
+    let loaded = load_dictionary(loading, &args, cache_dir, ciphertext.as_deref())?;
 
-    let wordlist_content = match args.wordlist {
+    let scorer = if args.sort || args.top.is_some() {
+        loading.text("Building scorer...".to_string());
+        let scorer = Scorer::new(loaded.dictionary.as_ref(), &loaded.alphabet);
+        loading.success("Sorting solutions by score".to_string());
+        Some(scorer)
+    } else {
+        None
+    };
+
+    let Some(ciphertext) = ciphertext else {
+        return solve_batch(loading, &args, &loaded, scorer.as_ref(), start);
+    };
+
+    loading.text("Parsing and mapping input words...".to_string());
+    let mut printer = Printer::new(args.format, &ciphertext);
+    let (solutions, stopped) = solve_ciphertext(
+        loading,
+        &args,
+        &loaded,
+        scorer.as_ref(),
+        &ciphertext,
+        &mut printer,
+    )?;
+    if args.interactive {
+        return Ok(());
+    }
+    printer.finish(solutions, start.elapsed(), stopped);
+
+    match (solutions, stopped) {
+        (0, None) => loading.fail("No solutions found.".to_string()),
+        (0, Some(stop)) => loading.fail(format!("No solutions found, {}", stopped_by(stop))),
+        (_, None) => loading.success(format!("Finished! ({solutions} solutions)")),
+        (_, Some(stop)) => loading.warn(format!(
+            "Finished! ({solutions} solutions, {})",
+            stopped_by(stop)
+        )),
+    }
+
+    Ok(())
+}
+
+/// Dictionary of the wordlist, and the alphabet it was cleaned into
+struct LoadedDictionary {
+    dictionary: Box<dyn PatternLookup>,
+    alphabet: Alphabet,
+    /// Wordlist that only the patterns of the ciphertext were kept from, which has to be read again for all words
+    partial: Option<PathBuf>,
+}
+
+/// Dictionary of the wordlist, from the cache if it was cached before.
+/// The built-in wordlist with the default alphabet was already turned into a dictionary when compiling.
+/// Without a cache, only the patterns of the ciphertext are kept if nothing else needs the other words
+fn load_dictionary(
+    loading: &Loading,
+    args: &Args,
+    cache_dir: Option<PathBuf>,
+    ciphertext: Option<&str>,
+) -> Result<LoadedDictionary, Box<dyn Error>> {
+    // Metadata is read first, so a wordlist that changes while reading it won't match its cache
+    let wordlist = match &args.wordlist {
         Some(path) => {
             loading.info(format!("Using wordlist from {path:?}"));
-            read_to_string(path)?
+            Some(WordlistFile::new(Path::new(path))?)
         }
         None => {
             loading.info("Using built-in english wordlist".to_string());
-            include_str!("../wordlist/english.txt").to_string()
+            None
+        }
+    };
+
+    // Parse args. The letters of a custom wordlist are found in the cache, or when reading it
+    let alphabet = match (args.alphabet.as_deref(), &wordlist) {
+        (None, _) => Some(Alphabet::default()),
+        (Some("wordlist"), Some(_)) => None,
+        (Some("wordlist"), None) => Some(Alphabet::from_wordlist(BUILTIN_WORDLIST)?),
+        (Some(alphabet), _) => Some(Alphabet::parse(alphabet)?),
+    };
+    let show_alphabet = |alphabet: &Alphabet| {
+        if args.alphabet.is_some() {
+            loading.info(format!("Using alphabet: {:?}", alphabet.to_string()));
         }
     };
 
+    let Some(wordlist) = &wordlist else {
+        let alphabet = alphabet.expect("only unknown for a custom wordlist");
+        show_alphabet(&alphabet);
+        let dictionary = if alphabet == Alphabet::default() {
+            builtin_dictionary()
+        } else {
+            loading.text("Finding patterns in wordlist...".to_string());
+            load_wordlist(BUILTIN_WORDLIST, &alphabet)
+        };
+        loading.success(format!("Loaded {} unique patterns", dictionary.len()));
+        return Ok(LoadedDictionary {
+            dictionary: Box::new(dictionary),
+            alphabet,
+            partial: None,
+        });
+    };
+
     // Try loading from cache
-    let dictionary = if args.no_cache {
-        loading.warn("Dictionary cache disabled".to_string());
-        None
-    } else {
+    let cache_dir = match cache_dir {
+        _ if args.no_cache => {
+            loading.warn("Dictionary cache disabled".to_string());
+            None
+        }
+        None => {
+            loading.warn(format!(
+                "No cache directory found, dictionary cache disabled (set one with --cache-dir or {CACHE_DIR_ENV})"
+            ));
+            None
+        }
+        Some(cache_dir) => Some(cache_dir),
+    };
+    if let Some(cache_dir) = &cache_dir {
         loading.text("Loading dictionary cache...".to_string());
-        load_cached_dictionary(&wordlist_content)
+        match load_cached_dictionary(cache_dir, wordlist, alphabet.as_ref()) {
+            Ok((dictionary, alphabet)) => {
+                show_alphabet(&alphabet);
+                loading.success(format!(
+                    "Loaded {} unique patterns (from cache)",
+                    dictionary.len()
+                ));
+                return Ok(LoadedDictionary {
+                    dictionary: Box::new(dictionary),
+                    alphabet,
+                    partial: None,
+                });
+            }
+            Err(CacheError::Missing) => {}
+            Err(e) => loading.warn(format!("Dictionary cache is {e}, rebuilding it")),
+        }
+    }
+
+    // Cache not loaded, so read the wordlist while hashing it for the cache
+    let from_wordlist = alphabet.is_none();
+    let alphabet = match alphabet {
+        Some(alphabet) => alphabet,
+        None => Alphabet::read_wordlist(open_wordlist(&wordlist.path)?)?,
+    };
+    show_alphabet(&alphabet);
+    let needed = match (&cache_dir, ciphertext) {
+        (None, Some(ciphertext)) => needed_patterns(args, ciphertext, &alphabet),
+        _ => None,
     };
+    loading.text("Finding patterns in wordlist...".to_string());
+    let mut hash = md5::Context::new();
+    let reader = decompress(HashingReader::new(File::open(&wordlist.path)?, &mut hash))?;
+    let dictionary = read_wordlist(reader, &alphabet, |pattern| {
+        needed
+            .as_ref()
+            .is_none_or(|needed| needed.contains(pattern))
+    })?;
+    let hash = hash.compute().0;
 
-    let dictionary = if let Some(dictionary) = dictionary {
-        // Cache loaded
+    if needed.is_some() {
         loading.success(format!(
-            "Loaded {} unique patterns (from cache)",
+            "Loaded {} unique patterns (only those in the ciphertext)",
             dictionary.len()
         ));
-        dictionary
     } else {
-        // Cache not loaded
-        loading.text("Finding patterns in wordlist...".to_string());
-        let dictionary = load_wordlist(&wordlist_content);
         loading.success(format!("Loaded {} unique patterns", dictionary.len()));
+    }
 
-        if !args.no_cache {
-            // Save cache
-            save_cached_dictionary(&wordlist_content, &dictionary)?;
-            loading.success("Saved dictionary cache".to_string());
+    if let Some(cache_dir) = &cache_dir {
+        // Save cache, but still solve if it can't be written
+        match save_cached_dictionary(
+            cache_dir,
+            wordlist,
+            hash,
+            &alphabet,
+            from_wordlist,
+            &dictionary,
+        ) {
+            Ok(()) => loading.success("Saved dictionary cache".to_string()),
+            Err(e) => loading.warn(format!("Could not save dictionary cache: {e}")),
         }
-        dictionary
-    };
+    }
+    Ok(LoadedDictionary {
+        dictionary: Box::new(dictionary),
+        alphabet,
+        partial: needed.map(|_| wordlist.path.clone()),
+    })
+}
 
-    loading.text("Parsing and mapping input words...".to_string());
+/// Patterns of the cipher words, when the search only looks up whole words with exactly these patterns.
+/// Splitting without spaces, homophones and scoring need other words of the dictionary too
+fn needed_patterns(args: &Args, ciphertext: &str, alphabet: &Alphabet) -> Option<HashSet<String>> {
+    if args.ignore_spaces || max_homophones(args) > 1 || args.sort || args.top.is_some() {
+        return None;
+    }
+    // Errors are shown when solving, with all words
+    let (ciphertext_clean, _) = clean_ciphertext(args, ciphertext, alphabet).ok()?;
+
+    Some(ciphertext_clean.split_whitespace().map(normalize).collect())
+}
 
-    let ciphertext = match args.ciphertext.string {
-        Some(ciphertext) => {
+/// List, inspect or remove the cached dictionaries
+fn cache_command(
+    loading: &Loading,
+    cache_dir: &Path,
+    command: &CacheCommand,
+) -> Result<(), Box<dyn Error>> {
+    match command {
+        CacheCommand::List => {
+            let entries = list_entries(cache_dir)?;
+            for entry in &entries {
+                let status = match &entry.header {
+                    Ok(header) => format!(
+                        "{} patterns from {}",
+                        header.entries,
+                        header.wordlist.path.display()
+                    ),
+                    Err(e) => e.to_string(),
+                };
+                println!(
+                    "{}\t{}\t{} ago\t{status}",
+                    entry.path.file_name().unwrap_or_default().to_string_lossy(),
+                    format_size(entry.size),
+                    format_age(entry.age()),
+                );
+            }
+            loading.success(format!("{} cached dictionaries", entries.len()));
+        }
+        CacheCommand::Info => {
+            let entries = list_entries(cache_dir)?;
+            let unusable = entries.iter().filter(|entry| entry.header.is_err()).count();
+            println!("Directory: {}", cache_dir.display());
+            println!("Entries:   {} ({unusable} stale or corrupt)", entries.len());
+            println!(
+                "Size:      {}",
+                format_size(entries.iter().map(|entry| entry.size).sum())
+            );
+            if let Some(oldest) = entries.last() {
+                println!("Oldest:    last used {} ago", format_age(oldest.age()));
+            }
+        }
+        CacheCommand::Clear => {
+            let removed = remove_entries(cache_dir, None)?;
+            loading.success(format!(
+                "Removed {} cached dictionaries ({})",
+                removed.len(),
+                format_size(removed.iter().map(|entry| entry.size).sum())
+            ));
+        }
+        CacheCommand::Prune { older_than } => {
+            let removed = remove_entries(cache_dir, Some(*older_than))?;
+            loading.success(format!(
+                "Removed {} cached dictionaries not used for {} ({})",
+                removed.len(),
+                format_age(*older_than),
+                format_size(removed.iter().map(|entry| entry.size).sum())
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Size in bytes, in the largest unit that keeps it above 1 (example: "1.5 MB")
+fn format_size(size: u64) -> String {
+    let mut size = size as f64;
+    for unit in ["B", "KB", "MB"] {
+        if size < 1000.0 {
+            return format!("{size:.1} {unit}").replace(".0 B", " B");
+        }
+        size /= 1000.0;
+    }
+    format!("{size:.1} GB")
+}
+
+/// Duration in the largest whole unit (example: "3 days")
+fn format_age(age: Duration) -> String {
+    let seconds = age.as_secs();
+    let (amount, unit) = match seconds {
+        0..60 => (seconds, "second"),
+        60..3600 => (seconds / 60, "minute"),
+        3600..86400 => (seconds / 3600, "hour"),
+        _ => (seconds / 86400, "day"),
+    };
+    let plural = if amount == 1 { "" } else { "s" };
+    format!("{amount} {unit}{plural}")
+}
+
+/// Ciphertext from the string or file argument, or otherwise from stdin
+fn read_ciphertext(loading: &Loading, args: &Args) -> Result<String, Box<dyn Error>> {
+    let from_stdin = args.ciphertext.string.is_none()
+        && args
+            .ciphertext
+            .file
+            .as_ref()
+            .is_none_or(|path| path == Path::new("-"));
+    let ciphertext = match (&args.ciphertext.string, &args.ciphertext.file) {
+        (Some(ciphertext), _) => {
             loading.info(format!("Input string: {ciphertext:?}"));
-            ciphertext
+            ciphertext.clone()
         }
-        None => {
-            let path = args.ciphertext.file.unwrap();
+        (None, Some(path)) if !from_stdin => {
             loading.info(format!("Input file: {path:?}"));
             read_to_string(path)?
         }
+        _ => {
+            if args.interactive {
+                return Err("Interactive mode reads commands from stdin, so give the ciphertext with --string or --file".into());
+            }
+            if stdin().is_terminal() && args.ciphertext.file.is_none() {
+                return Err(
+                    "No ciphertext given, use --string, --file or pipe it into stdin".into(),
+                );
+            }
+            loading.info("Input from stdin".to_string());
+            io::read_to_string(stdin())?
+        }
+    };
+
+    Ok(ciphertext)
+}
+
+/// Solve every input on its own with the same dictionary, where the inputs are all files in a directory
+/// or all lines of the ciphertext. With multiple threads, every thread solves the next input when it is done
+fn solve_batch(
+    loading: &Loading,
+    args: &Args,
+    loaded: &LoadedDictionary,
+    scorer: Option<&Scorer>,
+    start: Instant,
+) -> Result<(), Box<dyn Error>> {
+    let inputs = match &args.ciphertext.file {
+        Some(path) if path.is_dir() => {
+            loading.info(format!("Input directory: {path:?}"));
+            let mut paths = fs::read_dir(path)?
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<Result<Vec<_>, _>>()?;
+            paths.retain(|path| path.is_file());
+            paths.sort();
+
+            paths
+                .into_iter()
+                .map(|path| {
+                    let id = path.file_name().unwrap_or_default().to_string_lossy();
+                    Ok((id.to_string(), read_to_string(&path)?))
+                })
+                .collect::<Result<Vec<_>, io::Error>>()?
+        }
+        _ => read_ciphertext(loading, args)?
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(i, line)| ((i + 1).to_string(), line.to_string()))
+            .collect(),
+    };
+
+    let threads = match args.threads {
+        0 => thread::available_parallelism()?.get(),
+        threads => threads,
+    };
+    loading.success(format!(
+        "Solving {} inputs with {threads} threads",
+        inputs.len()
+    ));
+    loading.text("Solving inputs...".to_string());
+
+    // Inputs are solved at the same time instead of splitting up their search, and only report their results
+    let args = Args {
+        threads: 1,
+        ..args.clone()
     };
+    let hidden = Loading::hidden();
+    let mut printer = Printer::new(args.format, "");
+    let next_input = AtomicUsize::new(0);
+    let total = AtomicUsize::new(0);
+    let truncated = AtomicUsize::new(0);
+    let first_stop = OnceLock::new();
+
+    thread::scope(|s| {
+        for _ in 0..threads.min(inputs.len()) {
+            s.spawn(|| {
+                while let Some((id, ciphertext)) =
+                    inputs.get(next_input.fetch_add(1, Ordering::Relaxed))
+                {
+                    let mut printer = printer.for_input(id, ciphertext);
+                    match solve_ciphertext(&hidden, &args, loaded, scorer, ciphertext, &mut printer)
+                    {
+                        Ok((solutions, Some(stop))) => {
+                            total.fetch_add(solutions, Ordering::Relaxed);
+                            truncated.fetch_add(1, Ordering::Relaxed);
+                            let _ = first_stop.set(stop);
+                            loading
+                                .warn(format!("{id}: {solutions} solutions, {}", stopped_by(stop)));
+                        }
+                        Ok((0, None)) => loading.warn(format!("{id}: No solutions found")),
+                        Ok((solutions, None)) => {
+                            total.fetch_add(solutions, Ordering::Relaxed);
+                            loading.success(format!("{id}: {solutions} solutions"));
+                        }
+                        Err(e) => loading.fail(format!("{id}: {e}")),
+                    }
+                }
+            });
+        }
+    });
+
+    let solutions = total.into_inner();
+    printer.finish(solutions, start.elapsed(), first_stop.get().copied());
+    match truncated.into_inner() {
+        0 => loading.success(format!(
+            "Finished! ({solutions} solutions for {} inputs)",
+            inputs.len()
+        )),
+        truncated => loading.warn(format!(
+            "Finished! ({solutions} solutions for {} inputs, {truncated} stopped early)",
+            inputs.len()
+        )),
+    }
+
+    Ok(())
+}
+
+/// Solve one ciphertext and print its solutions. Returns the number of solutions that were found,
+/// and the limit that stopped the search if it was not complete
+fn solve_ciphertext(
+    loading: &Loading,
+    args: &Args,
+    loaded: &LoadedDictionary,
+    scorer: Option<&Scorer>,
+    ciphertext: &str,
+    printer: &mut Printer,
+) -> Result<(usize, Option<Stop>), Box<dyn Error>> {
+    let timeout = args.timeout.map(Duration::try_from_secs_f64).transpose()?;
+    let budget = Arc::new(Budget::new(args.max_solutions, timeout, args.max_nodes));
+    let dictionary = loaded.dictionary.as_ref();
+    let mut alphabet = loaded.alphabet.clone();
 
     // Parse input
-    let ciphertext_clean = clean_input(&ciphertext);
+    let (ciphertext_clean, symbols) = clean_ciphertext(args, ciphertext, &alphabet)?;
+    if let Some(symbols) = &symbols {
+        loading.success(format!("Found {} different symbols", symbols.tokens.len()));
+        alphabet = symbols.alphabet(&alphabet);
+    }
+    if ciphertext_clean.is_empty() {
+        return Err("Ciphertext has no letters to solve".into());
+    }
 
-    let mut cipher_words = input_to_words(&ciphertext_clean, &dictionary)?;
+    let (starting_key, exclusions) = match &args.key {
+        Some(key) => {
+            loading.info(format!("Using starting key: {key:?}"));
+            match &symbols {
+                Some(symbols) => symbols.parse_key(key, &alphabet, max_homophones(args))?,
+                None => parse_key(key, &alphabet, max_homophones(args))?,
+            }
+        }
+        None => {
+            loading.info("Using empty starting key".to_string());
+            (HashMap::new(), Exclusions::default())
+        }
+    };
 
-    loading.success(format!("Parsed {} input words", cipher_words.len()));
+    // Solve once from the starting key, or once from every place the crib fits
+    let starting_keys = match &args.crib {
+        Some(crib) => {
+            let crib = clean_input(crib, &alphabet);
+            loading.info(format!("Using crib: {crib:?}"));
+            let placements = place_crib(
+                &ciphertext_clean,
+                &crib,
+                &alphabet,
+                &starting_key,
+                max_homophones(args),
+                args.ignore_spaces,
+            )?;
+            let placements = placements
+                .into_iter()
+                .filter(|placement| {
+                    !(placement.key.iter()).any(|(&a, &b)| exclusions.excludes(a, b))
+                })
+                .collect::<Vec<_>>();
+            if placements.is_empty() {
+                return Err(format!("Crib {crib:?} doesn't fit anywhere in the ciphertext").into());
+            }
+            loading.success(format!("Found {} placements of the crib", placements.len()));
 
-    loading.text("Pruning...".to_string());
-    // Order by length, longest first
-    cipher_words.sort_by(|a, b| b.word.len().cmp(&a.word.len()));
-    // Remove impossible words
-    prune(&mut cipher_words);
-    loading.success("Pruned impossible words".to_string());
+            placements
+                .into_iter()
+                .map(|placement| (Some(placement.to_string()), placement.key))
+                .collect()
+        }
+        None => vec![(None, starting_key.clone())],
+    };
 
-    loading.end();
-    loading.info("Starting to find solutions...".to_string());
+    printer.symbols = symbols.clone();
 
-    let (tx, rx) = mpsc::channel();
+    if args.interactive {
+        let max_homophones = max_homophones(args);
+        let words = input_to_words(&ciphertext_clean, dictionary, args.unknown, max_homophones)?;
+        let mut session = Session::new(&ciphertext_clean, words);
+        session.alphabet = alphabet;
+        session.max_unknown = args.unknown;
+        session.max_homophones = max_homophones;
+        session.max_solutions = args.max_solutions;
+        session.timeout = timeout;
+        session.max_nodes = args.max_nodes;
+        session.set_key(starting_key, exclusions);
 
-    thread::spawn(move || {
-        let mut solver = Solver::new(&cipher_words);
-        solver.solve(starting_key, Some(&tx));
-    });
+        interactive::run(loading, &mut session, printer, stdin().lock())?;
+        return Ok((0, None));
+    }
+
+    let mut ranked = Vec::new();
+    let mut receive_solutions = |rx: mpsc::Receiver<Solution>| {
+        let mut solutions = 0;
+        for mut solution in rx {
+            let (plaintext, unknown) = if solution.segments.is_empty() {
+                solution.apply_with_unknown(&ciphertext_clean)
+            } else {
+                solution.apply_with_unknown(&solution.segments.join(" "))
+            };
+            let score = scorer.map(|scorer| scorer.score(&plaintext));
+            let (plaintext, unknown) = if args.preserve {
+                solution.apply_formatted_with_unknown(ciphertext)
+            } else {
+                (plaintext, unknown)
+            };
+            solution.unknown_positions = unknown;
+
+            if args.fill_key {
+                solution.fill_key();
+            }
+            solutions += 1;
+
+            if score.is_some() {
+                // Keep solutions to print them in order at the end
+                solution.score = score;
+                ranked.push((solution, plaintext));
+
+                if let Some(top) = args.top {
+                    if ranked.len() >= top.max(1) * 2 {
+                        sort_by_score(&mut ranked);
+                        ranked.truncate(top);
+                    }
+                }
+            } else {
+                // Print solutions as they are found
+                printer.print(&solution, &plaintext);
+            }
+        }
+        solutions
+    };
 
     let mut solutions = 0;
-    for mut solution in rx {
-        // Print solutions as they are found
-        let plaintext = solution.apply(&ciphertext_clean);
+    if !args.statistical {
+        match start_dictionary_attack(
+            loading,
+            args,
+            dictionary,
+            &alphabet,
+            &ciphertext_clean,
+            Search {
+                starting_keys,
+                exclusions: exclusions.clone(),
+                budget: budget.clone(),
+            },
+        ) {
+            Ok(rx) => solutions = receive_solutions(rx),
+            Err(e) => {
+                // Show cipher words in the error in their original symbols
+                let e = match &symbols {
+                    Some(symbols) => symbols.decode(&e.to_string()).into(),
+                    None => e,
+                };
+                if args.no_fallback {
+                    return Err(e);
+                }
+                loading.warn(e);
+            }
+        }
+    }
 
-        if args.fill_key {
-            solution.fill_key();
+    // Nothing is left of the budget to fall back with
+    let stopped = budget.stopped().is_some();
+    if solutions == 0 && (args.statistical || (!args.no_fallback && !stopped)) {
+        if !args.statistical {
+            loading.warn("Falling back to statistical solver".to_string());
         }
+        loading.end();
+        loading.info("Starting statistical solver...".to_string());
 
-        let formatted_solution = if std::io::stdout().is_terminal() {
-            solution.format_hyperlink(&ciphertext)
-        } else {
-            solution.to_string()
+        let (tx, rx) = mpsc::channel();
+        let model = match &loaded.partial {
+            Some(path) => {
+                // Not all words were kept, so they are counted while reading the wordlist again
+                loading.text("Counting quadgrams in wordlist...".to_string());
+                NgramModel::read_wordlist(open_wordlist(path)?, &alphabet)?
+            }
+            None => NgramModel::from_dictionary(dictionary, &alphabet),
         };
-        println!("{formatted_solution} -> {plaintext}");
-        solutions += 1;
+        let mut climber = HillClimber::new(model);
+        climber.restarts = args.restarts;
+        climber.max_homophones = max_homophones(args);
+        climber.exclusions = exclusions;
+        climber.budget = budget.clone();
+        let ciphertext_clean = ciphertext_clean.clone();
+        thread::spawn(move || {
+            climber.solve(&ciphertext_clean, starting_key, Some(&tx));
+        });
+        solutions = receive_solutions(rx);
+    }
+
+    if scorer.is_some() {
+        sort_by_score(&mut ranked);
+        ranked.truncate(args.top.unwrap_or(ranked.len()));
+        for (solution, plaintext) in &ranked {
+            printer.print(solution, plaintext);
+        }
+    }
+
+    Ok((solutions, budget.stopped()))
+}
+
+/// Ciphertext cleaned into words of letters, or of symbols if it is written in symbols
+fn clean_ciphertext(
+    args: &Args,
+    ciphertext: &str,
+    alphabet: &Alphabet,
+) -> Result<(String, Option<Symbols>), String> {
+    if args.symbols || args.delimiter.is_some() {
+        let (symbols, ciphertext_clean) = Symbols::tokenize(ciphertext, args.delimiter.as_deref())?;
+        Ok((ciphertext_clean, Some(symbols)))
+    } else {
+        Ok((clean_input(ciphertext, alphabet), None))
     }
+}
 
-    if solutions == 0 {
-        loading.fail("No solutions found.".to_string());
+/// What is searched for in the dictionary attack
+struct Search {
+    /// Keys to start from, with the crib placement they came from
+    starting_keys: Vec<(Option<String>, HashMap<char, char>)>,
+    exclusions: Exclusions,
+    budget: Arc<Budget>,
+}
+
+/// Message for a search that was stopped by a limit
+fn stopped_by(stop: Stop) -> String {
+    let flag = match stop {
+        Stop::Solutions => "--max-solutions",
+        Stop::Timeout => "--timeout",
+        Stop::Nodes => "--max-nodes",
+    };
+    format!("stopped early by {flag}")
+}
+
+/// Start solving with the wordlist in another thread, and return where the solutions will be sent.
+/// The search is done once for every starting key, and their solutions get the crib placement it came from
+fn start_dictionary_attack(
+    loading: &Loading,
+    args: &Args,
+    dictionary: &dyn PatternLookup,
+    alphabet: &Alphabet,
+    ciphertext_clean: &str,
+    search: Search,
+) -> Result<mpsc::Receiver<Solution>, Box<dyn Error>> {
+    let (tx, rx) = mpsc::channel();
+    let Search {
+        starting_keys,
+        exclusions,
+        budget,
+    } = search;
+
+    if args.ignore_spaces {
+        let mut solver = SegmentSolver::new(ciphertext_clean, dictionary)?;
+        solver.alphabet = alphabet.clone();
+        solver.exclusions = exclusions;
+        solver.budget = budget;
+        loading.success(format!(
+            "Found {} possible input words without spaces",
+            solver.segments.iter().map(|s| s.len()).sum::<usize>()
+        ));
+
+        loading.end();
+        loading.info("Starting to find solutions...".to_string());
+
+        thread::spawn(move || {
+            for (crib, starting_key) in starting_keys {
+                solve_labeled(&tx, crib, |tx| solver.solve(starting_key, Some(tx)));
+            }
+        });
     } else {
-        loading.success(format!("Finished! ({solutions} solutions)"));
+        let max_homophones = max_homophones(args);
+        if max_homophones > 1 {
+            loading.info("Allowing homophones".to_string());
+        }
+        let mut cipher_words =
+            input_to_words(ciphertext_clean, dictionary, args.unknown, max_homophones)?;
+
+        loading.success(format!("Parsed {} input words", cipher_words.len()));
+        let forced_unknown = count_unknown(&cipher_words);
+        if forced_unknown > 0 {
+            loading.warn(format!("{forced_unknown} input words not in dictionary"));
+        }
+
+        if forced_unknown < args.unknown {
+            // Any word may still be skipped, so words can't rule out letters for each other
+            loading.warn("Skipped pruning, because more words may be unknown".to_string());
+        } else {
+            loading.text("Pruning...".to_string());
+            // Remove impossible words
+            prune(&mut cipher_words, alphabet, max_homophones, &exclusions);
+            loading.success("Pruned impossible words".to_string());
+        }
+
+        loading.end();
+        loading.info("Starting to find solutions...".to_string());
+
+        let mut solver = Solver::new(&cipher_words);
+        solver.max_unknown = args.unknown;
+        solver.alphabet = alphabet.clone();
+        solver.max_homophones = max_homophones;
+        solver.exclusions = exclusions;
+        solver.budget = budget;
+        solver.ordered = args.ordered;
+        solver.threads = match args.threads {
+            0 => thread::available_parallelism()?.get(),
+            threads => threads,
+        };
+        if solver.threads > 1 {
+            loading.info(format!("Searching with {} threads", solver.threads));
+        }
+
+        thread::spawn(move || {
+            for (crib, starting_key) in starting_keys {
+                solve_labeled(&tx, crib, |tx| solver.solve(starting_key, Some(tx)));
+            }
+        });
     }
 
-    Ok(())
+    Ok(rx)
+}
+
+/// Run a search, and pass on its solutions with the crib placement that it started from
+fn solve_labeled(
+    tx: &mpsc::Sender<Solution>,
+    crib: Option<String>,
+    solve: impl FnOnce(&mpsc::Sender<Solution>) + Send,
+) {
+    let Some(crib) = crib else {
+        return solve(tx);
+    };
+
+    let (crib_tx, crib_rx) = mpsc::channel();
+    thread::scope(|s| {
+        s.spawn(move || solve(&crib_tx));
+        for mut solution in crib_rx {
+            solution.crib = Some(crib.clone());
+            if tx.send(solution).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+/// Most cipher letters that may map to the same plaintext letter, 1 unless homophones are allowed
+fn max_homophones(args: &Args) -> usize {
+    match args.max_homophones {
+        Some(max) => max.max(1),
+        None if args.homophonic => MAX_LETTERS,
+        None => 1,
+    }
+}
+
+/// Highest score first, and otherwise in order of the plaintext and key so the order is always the same
+fn sort_by_score(solutions: &mut [(Solution, String)]) {
+    solutions.sort_by(|(a, a_plaintext), (b, b_plaintext)| {
+        let score = |solution: &Solution| solution.score.unwrap_or(f64::NEG_INFINITY);
+        score(b)
+            .total_cmp(&score(a))
+            .then_with(|| a_plaintext.cmp(b_plaintext))
+            .then_with(|| a.to_string().cmp(&b.to_string()))
+    });
 }
@@ -0,0 +1,219 @@
+use std::{
+    fs::File,
+    io::{self, Write},
+};
+
+use memmap2::Mmap;
+
+use crate::{Dictionary, PatternLookup, Patterns};
+
+/// Bytes of one pattern in the index: where its string is, and which words have it
+const PATTERN_SIZE: usize = 4 * 8;
+/// Bytes of one word in the index: where its string is, and how often it occurs
+const WORD_SIZE: usize = 3 * 8;
+/// Bytes of the counts at the start
+const COUNTS_SIZE: usize = 3 * 8;
+
+/// Dictionary in a file that is memory-mapped, so words are read from the file only when they are looked up.
+///
+/// The file starts with the number of patterns, words and bytes of strings, followed by an index of patterns
+/// sorted by their string, an index of words grouped by pattern, and all strings after each other.
+/// Numbers are little-endian `u64`s, and strings are found by their offset and length in the strings
+pub struct MappedDictionary {
+    mmap: Mmap,
+    /// Where the dictionary starts in the file, after anything that comes before it
+    start: usize,
+    patterns: usize,
+    words: usize,
+}
+impl MappedDictionary {
+    /// Map the dictionary that starts at `start` in the file, and check that its size is right.
+    /// The file should not be changed while it is mapped, so it is replaced instead of written to
+    pub fn open(file: &File, start: u64) -> Result<Self, String> {
+        // SAFETY: cache files are only replaced with a rename. Only the header before `start` may be written to,
+        // which is never read from the map
+        let mmap = unsafe { Mmap::map(file) }.map_err(|e| e.to_string())?;
+        let start = usize::try_from(start).map_err(|e| e.to_string())?;
+
+        let mut dictionary = MappedDictionary {
+            mmap,
+            start,
+            patterns: 0,
+            words: 0,
+        };
+        let counts = (0..3)
+            .map(|i| dictionary.number(start + i * 8))
+            .collect::<Option<Vec<_>>>()
+            .ok_or("file is too short")?;
+        let (patterns, words, strings) = (counts[0], counts[1], counts[2]);
+
+        let expected = [
+            patterns.checked_mul(PATTERN_SIZE),
+            words.checked_mul(WORD_SIZE),
+            Some(strings),
+        ]
+        .into_iter()
+        .try_fold(start + COUNTS_SIZE, |size, part| size.checked_add(part?));
+        if expected != Some(dictionary.mmap.len()) {
+            return Err(format!(
+                "{} bytes, expected {}",
+                dictionary.mmap.len(),
+                expected.map_or("more".to_string(), |size| size.to_string())
+            ));
+        }
+
+        dictionary.patterns = patterns;
+        dictionary.words = words;
+        Ok(dictionary)
+    }
+
+    fn u64_at(&self, position: usize) -> Option<u64> {
+        let bytes = self.mmap.get(position..position.checked_add(8)?)?;
+        Some(u64::from_le_bytes(bytes.try_into().ok()?))
+    }
+
+    /// Offset or length at a position
+    fn number(&self, position: usize) -> Option<usize> {
+        usize::try_from(self.u64_at(position)?).ok()
+    }
+
+    fn string(&self, offset: usize, length: usize) -> Option<&str> {
+        let strings =
+            self.start + COUNTS_SIZE + self.patterns * PATTERN_SIZE + self.words * WORD_SIZE;
+        let start = strings.checked_add(offset)?;
+        std::str::from_utf8(self.mmap.get(start..start.checked_add(length)?)?).ok()
+    }
+
+    /// String of the pattern at index `i`, and the range of its words
+    fn pattern(&self, i: usize) -> Option<(&str, usize, usize)> {
+        let position = self.start + COUNTS_SIZE + i * PATTERN_SIZE;
+        let [offset, length, first_word, words] =
+            [0, 1, 2, 3].map(|field| self.number(position + field * 8));
+        Some((self.string(offset?, length?)?, first_word?, words?))
+    }
+
+    fn word(&self, i: usize) -> Option<(&str, u64)> {
+        let position = self.start + COUNTS_SIZE + self.patterns * PATTERN_SIZE + i * WORD_SIZE;
+        let [offset, length] = [0, 1].map(|field| self.number(position + field * 8));
+        Some((self.string(offset?, length?)?, self.u64_at(position + 16)?))
+    }
+
+    fn words_of(&self, first_word: usize, words: usize) -> Vec<(&str, u64)> {
+        (first_word..first_word.saturating_add(words).min(self.words))
+            .filter_map(|i| self.word(i))
+            .collect()
+    }
+}
+impl PatternLookup for MappedDictionary {
+    fn words(&self, pattern: &str) -> Option<Vec<(&str, u64)>> {
+        // Binary search, because the patterns are sorted
+        let (mut low, mut high) = (0, self.patterns);
+        while low < high {
+            let middle = low + (high - low) / 2;
+            let (found, first_word, words) = self.pattern(middle)?;
+            match found.cmp(pattern) {
+                std::cmp::Ordering::Less => low = middle + 1,
+                std::cmp::Ordering::Greater => high = middle,
+                std::cmp::Ordering::Equal => return Some(self.words_of(first_word, words)),
+            }
+        }
+        None
+    }
+
+    fn patterns(&self) -> Patterns<'_> {
+        Box::new((0..self.patterns).filter_map(|i| {
+            let (pattern, first_word, words) = self.pattern(i)?;
+            Some((pattern, self.words_of(first_word, words)))
+        }))
+    }
+
+    fn len(&self) -> usize {
+        self.patterns
+    }
+}
+
+/// Write a dictionary in the format of `MappedDictionary`
+pub fn write_mapped(dictionary: &Dictionary, writer: &mut impl Write) -> io::Result<()> {
+    let mut patterns = dictionary.iter().collect::<Vec<_>>();
+    patterns.sort_by_key(|&(pattern, _)| pattern);
+    let words = patterns.iter().map(|(_, words)| words.len()).sum::<usize>();
+
+    let mut pattern_index = Vec::with_capacity(patterns.len() * PATTERN_SIZE);
+    let mut word_index = Vec::with_capacity(words * WORD_SIZE);
+    let mut strings = Vec::new();
+    let mut add_string = |string: &str, index: &mut Vec<u8>| {
+        index.extend((strings.len() as u64).to_le_bytes());
+        index.extend((string.len() as u64).to_le_bytes());
+        strings.extend(string.as_bytes());
+    };
+
+    let mut first_word = 0;
+    for (pattern, pattern_words) in patterns {
+        add_string(pattern, &mut pattern_index);
+        pattern_index.extend((first_word as u64).to_le_bytes());
+        pattern_index.extend((pattern_words.len() as u64).to_le_bytes());
+
+        // Most frequent first, like the candidates of a `Word`
+        let mut pattern_words = pattern_words.iter().collect::<Vec<_>>();
+        pattern_words.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(a.cmp(b)));
+        for &(word, count) in &pattern_words {
+            add_string(word, &mut word_index);
+            word_index.extend(count.to_le_bytes());
+        }
+        first_word += pattern_words.len();
+    }
+
+    for count in [dictionary.len(), words, strings.len()] {
+        writer.write_all(&(count as u64).to_le_bytes())?;
+    }
+    writer.write_all(&pattern_index)?;
+    writer.write_all(&word_index)?;
+    writer.write_all(&strings)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, fs};
+
+    use crate::{alphabet::Alphabet, input::input_to_words, load_wordlist};
+
+    use super::*;
+
+    #[test]
+    fn lookup_mapped() {
+        let path = std::env::temp_dir().join(format!("sub-solver-mapped-{}", std::process::id()));
+        let dictionary = load_wordlist("the\t10\nand\t20\nfew\nwords\na", &Alphabet::default());
+
+        let mut bytes = b"something before".to_vec();
+        write_mapped(&dictionary, &mut bytes).unwrap();
+        fs::write(&path, &bytes).unwrap();
+        let mapped = MappedDictionary::open(&File::open(&path).unwrap(), 16).unwrap();
+
+        assert_eq!(mapped.len(), dictionary.len());
+        assert_eq!(
+            mapped.words("ABC"),
+            Some(vec![("and", 20), ("the", 10), ("few", 1)])
+        );
+        assert_eq!(mapped.words("A"), Some(vec![("a", 1)]));
+        assert_eq!(mapped.words("ABCDE"), Some(vec![("words", 1)]));
+        assert_eq!(mapped.words("AA"), None);
+
+        let patterns = mapped
+            .patterns()
+            .map(|(pattern, words)| {
+                let words = words.into_iter().map(|(w, c)| (w.to_string(), c));
+                (pattern.to_string(), words.collect::<HashMap<_, _>>())
+            })
+            .collect::<Dictionary>();
+        assert_eq!(patterns, dictionary);
+
+        let words = input_to_words("x cbt", &mapped, 0, 1).unwrap();
+        assert_eq!(words[1].candidates, vec!["and", "the", "few"]);
+
+        // Cut off
+        fs::write(&path, &bytes[..bytes.len() - 1]).unwrap();
+        assert!(MappedDictionary::open(&File::open(&path).unwrap(), 16).is_err());
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,338 @@
+use std::{
+    collections::BTreeMap,
+    io::{stdout, IsTerminal},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::{budget::Stop, solve::Solution, symbols::Symbols};
+
+/// How solutions are printed to stdout
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Format {
+    /// Human readable "key -> plaintext" lines
+    #[default]
+    Text,
+    /// One JSON object with all solutions and a summary at the end
+    Json,
+    /// One JSON object per line for every solution, and a summary as the last line
+    Ndjson,
+    /// Comma-separated values with a header
+    Csv,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Record<'a> {
+    Solution {
+        /// Plaintext letter for every letter of the alphabet, '?' if unknown.
+        /// For symbols this is a table like "12:l,5:e,7:?"
+        key: String,
+        key_map: BTreeMap<String, char>,
+        plaintext: &'a str,
+        score: Option<f64>,
+        /// Positions in the plaintext of letters that are not known
+        unknown_positions: Vec<usize>,
+        /// Cipher words that were skipped as unknown
+        unknown_words: Vec<String>,
+        /// Where the crib was placed, if one was given (example: "word 3")
+        #[serde(skip_serializing_if = "Option::is_none")]
+        crib: Option<String>,
+        /// Id of the input in batch mode (example: "3" for the third line)
+        #[serde(skip_serializing_if = "Option::is_none")]
+        input: Option<&'a str>,
+    },
+    Summary {
+        solutions: usize,
+        seconds: f64,
+        /// If the whole search was done, instead of being stopped by a limit
+        complete: bool,
+        /// Limit that stopped the search early
+        #[serde(skip_serializing_if = "Option::is_none")]
+        stopped: Option<String>,
+    },
+}
+impl<'a> Record<'a> {
+    /// With `symbols`, the key is a table of symbols and letters, and cipher words are shown in symbols
+    pub fn solution(
+        solution: &Solution,
+        plaintext: &'a str,
+        symbols: Option<&Symbols>,
+        input: Option<&'a str>,
+    ) -> Self {
+        let cipher_letter = |c: char| match symbols.and_then(|symbols| symbols.token(c)) {
+            Some(token) => token.to_string(),
+            None => c.to_string(),
+        };
+
+        Record::Solution {
+            key: format_key(solution, symbols),
+            key_map: solution
+                .key
+                .iter()
+                .map(|(&a, &b)| (cipher_letter(a), b))
+                .collect(),
+            plaintext,
+            score: solution.score,
+            unknown_positions: solution.unknown_positions.clone(),
+            unknown_words: unknown_words(solution, symbols),
+            crib: solution.crib.clone(),
+            input,
+        }
+    }
+}
+
+/// Prints solutions to stdout in the chosen format
+pub struct Printer {
+    pub format: Format,
+    /// Cipher symbols, if the ciphertext is not written in letters
+    pub symbols: Option<Symbols>,
+    /// Id of the input that the solutions are for, in batch mode
+    pub input: Option<String>,
+    /// Original ciphertext, used for the CyberChef link in the text format
+    ciphertext: String,
+    /// Number of solutions printed, shared with the printers of other inputs
+    printed: Arc<AtomicUsize>,
+}
+impl Printer {
+    pub fn new(format: Format, ciphertext: &str) -> Self {
+        Printer {
+            format,
+            symbols: None,
+            input: None,
+            ciphertext: ciphertext.to_string(),
+            printed: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Printer for another input in the same output, so they can be printed at the same time in batch mode
+    pub fn for_input(&self, input: &str, ciphertext: &str) -> Self {
+        Printer {
+            format: self.format,
+            symbols: None,
+            input: Some(input.to_string()),
+            ciphertext: ciphertext.to_string(),
+            printed: Arc::clone(&self.printed),
+        }
+    }
+
+    pub fn print(&mut self, solution: &Solution, plaintext: &str) {
+        // Keep stdout locked, so no other input prints in between the separator and its solution
+        let _stdout = stdout().lock();
+        let printed = self.printed.fetch_add(1, Ordering::Relaxed);
+
+        match self.format {
+            Format::Text => self.print_text(solution, plaintext),
+            Format::Json => {
+                let separator = if printed == 0 {
+                    "{\"solutions\":["
+                } else {
+                    ","
+                };
+                print!(
+                    "{separator}{}",
+                    to_json(&Record::solution(
+                        solution,
+                        plaintext,
+                        self.symbols.as_ref(),
+                        self.input.as_deref()
+                    ))
+                );
+            }
+            Format::Ndjson => println!(
+                "{}",
+                to_json(&Record::solution(
+                    solution,
+                    plaintext,
+                    self.symbols.as_ref(),
+                    self.input.as_deref()
+                ))
+            ),
+            Format::Csv => {
+                if printed == 0 {
+                    println!("key,plaintext,score,unknown_positions,unknown_words,crib,input");
+                }
+                let positions = solution
+                    .unknown_positions
+                    .iter()
+                    .map(|i| i.to_string())
+                    .collect::<Vec<_>>();
+                println!(
+                    "{},{},{},{},{},{},{}",
+                    csv_field(&format_key(solution, self.symbols.as_ref())),
+                    csv_field(plaintext),
+                    solution.score.map(|s| s.to_string()).unwrap_or_default(),
+                    positions.join(" "),
+                    csv_field(&unknown_words(solution, self.symbols.as_ref()).join(" ")),
+                    solution.crib.as_deref().unwrap_or_default(),
+                    csv_field(self.input.as_deref().unwrap_or_default()),
+                );
+            }
+        }
+    }
+
+    /// Print the summary after all solutions, if the format has one.
+    /// `stopped` is the limit that stopped the search early, if any
+    pub fn finish(&mut self, solutions: usize, elapsed: Duration, stopped: Option<Stop>) {
+        let summary = Record::Summary {
+            solutions,
+            seconds: elapsed.as_secs_f64(),
+            complete: stopped.is_none(),
+            stopped: stopped.map(|stop| stop.to_string()),
+        };
+
+        match self.format {
+            Format::Json => {
+                if self.printed.load(Ordering::Relaxed) == 0 {
+                    print!("{{\"solutions\":[");
+                }
+                println!("],\"summary\":{}}}", to_json(&summary));
+            }
+            Format::Ndjson => println!("{}", to_json(&summary)),
+            Format::Text | Format::Csv => {}
+        }
+    }
+
+    fn print_text(&self, solution: &Solution, plaintext: &str) {
+        let formatted_solution = if self.symbols.is_some() {
+            format_key(solution, self.symbols.as_ref())
+        } else if std::io::stdout().is_terminal() {
+            solution.format_hyperlink(&self.ciphertext)
+        } else {
+            solution.to_string()
+        };
+
+        let mut line = format!("{formatted_solution} -> {plaintext}");
+        if let Some(input) = &self.input {
+            line = format!("{input}: {line}");
+        }
+        if !solution.unknown.is_empty() {
+            let unknown = unknown_words(solution, self.symbols.as_ref());
+            line += &format!(" (unknown: {})", unknown.join(", "));
+        }
+        if let Some(crib) = &solution.crib {
+            line += &format!(" (crib at {crib})");
+        }
+        if let Some(score) = solution.score {
+            line += &format!(" (score: {score:.2})");
+        }
+        println!("{line}");
+    }
+}
+
+/// Key as a string of letters, or as a table of symbols and letters
+fn format_key(solution: &Solution, symbols: Option<&Symbols>) -> String {
+    match symbols {
+        Some(symbols) => symbols.format_key(&solution.key),
+        None => solution.to_string(),
+    }
+}
+
+/// Cipher words that were skipped as unknown, in their original symbols
+fn unknown_words(solution: &Solution, symbols: Option<&Symbols>) -> Vec<String> {
+    match symbols {
+        Some(symbols) => solution.unknown.iter().map(|w| symbols.decode(w)).collect(),
+        None => solution.unknown.clone(),
+    }
+}
+
+fn to_json(record: &Record) -> String {
+    serde_json::to_string(record).expect("records are always valid JSON")
+}
+
+/// Quote a CSV field if it contains special characters
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn solution_record() {
+        let mut solution = Solution::new([('x', 'a'), ('c', 'f')].into());
+        solution.score = Some(-1.5);
+        let plaintext;
+        (plaintext, solution.unknown_positions) = solution.apply_with_unknown("x cbt");
+
+        assert_eq!(
+            to_json(&Record::solution(&solution, &plaintext, None, None)),
+            r#"{"type":"solution","key":"??f????????????????????a??","key_map":{"c":"f","x":"a"},"plaintext":"a f??","score":-1.5,"unknown_positions":[3,4],"unknown_words":[]}"#
+        );
+        assert!(
+            to_json(&Record::solution(&solution, &plaintext, None, Some("3")))
+                .ends_with(r#""unknown_words":[],"input":"3"}"#)
+        );
+        assert_eq!(
+            to_json(&Record::Summary {
+                solutions: 1,
+                seconds: 0.5,
+                complete: true,
+                stopped: None,
+            }),
+            r#"{"type":"summary","solutions":1,"seconds":0.5,"complete":true}"#
+        );
+        assert_eq!(
+            to_json(&Record::Summary {
+                solutions: 2,
+                seconds: 0.5,
+                complete: false,
+                stopped: Some(Stop::Timeout.to_string()),
+            }),
+            r#"{"type":"summary","solutions":2,"seconds":0.5,"complete":false,"stopped":"timeout"}"#
+        );
+    }
+
+    #[test]
+    fn preserved_record() {
+        let mut solution = Solution::new(
+            [
+                ('x', 'a'),
+                ('c', 'f'),
+                ('b', 'e'),
+                ('t', 'w'),
+                ('l', 'o'),
+                ('o', 'r'),
+                ('a', 'd'),
+                ('p', 's'),
+            ]
+            .into(),
+        );
+        let plaintext;
+        (plaintext, solution.unknown_positions) =
+            solution.apply_formatted_with_unknown("X cbt tloap?");
+        assert!(
+            to_json(&Record::solution(&solution, &plaintext, None, None)).ends_with(
+                r#""plaintext":"A few words?","score":null,"unknown_positions":[],"unknown_words":[]}"#
+            )
+        );
+
+        // Only the letter that the key doesn't know
+        solution.key.remove(&'p');
+        let plaintext;
+        (plaintext, solution.unknown_positions) =
+            solution.apply_formatted_with_unknown("X cbt tloap?");
+        assert!(
+            to_json(&Record::solution(&solution, &plaintext, None, None)).ends_with(
+                r#""plaintext":"A few word??","score":null,"unknown_positions":[10],"unknown_words":[]}"#
+            )
+        );
+    }
+
+    #[test]
+    fn csv_fields() {
+        assert_eq!(csv_field("a few"), "a few");
+        assert_eq!(csv_field("a, \"few\""), "\"a, \"\"few\"\"\"");
+    }
+}
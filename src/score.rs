@@ -0,0 +1,277 @@
+use std::{
+    collections::HashMap,
+    io::{self, BufRead},
+};
+
+use crate::{alphabet::Alphabet, read_words, PatternLookup};
+
+const NGRAM_SIZE: usize = 4;
+
+/// Letter quadgram statistics, used to measure how much a text looks like the language of the wordlist
+pub struct NgramModel {
+    /// Letters that the quadgrams consist of
+    pub alphabet: Alphabet,
+    log_probs: Vec<f64>,
+    floor: f64,
+}
+impl NgramModel {
+    /// Count all quadgrams inside of the given words, weighted by how often each word occurs
+    pub fn from_words<S: AsRef<str>>(
+        words: impl IntoIterator<Item = (S, u64)>,
+        alphabet: &Alphabet,
+    ) -> Self {
+        let mut counts = vec![0u64; alphabet.len().pow(NGRAM_SIZE as u32)];
+        let mut total = 0;
+
+        for (word, count) in words {
+            let indices = word
+                .as_ref()
+                .chars()
+                .filter_map(|c| alphabet.index(c))
+                .map(usize::from)
+                .collect::<Vec<_>>();
+            for window in indices.windows(NGRAM_SIZE) {
+                counts[Self::index(window, alphabet.len())] += count;
+                total += count;
+            }
+        }
+
+        let total = total.max(1) as f64;
+        let floor = (0.01 / total).log10();
+        let log_probs = counts
+            .into_iter()
+            .map(|count| {
+                if count == 0 {
+                    floor
+                } else {
+                    (count as f64 / total).log10()
+                }
+            })
+            .collect();
+
+        NgramModel {
+            alphabet: alphabet.clone(),
+            log_probs,
+            floor,
+        }
+    }
+
+    /// Count all quadgrams inside the words of a dictionary
+    pub fn from_dictionary(
+        dictionary: &(impl PatternLookup + ?Sized),
+        alphabet: &Alphabet,
+    ) -> Self {
+        Self::from_words(dictionary.patterns().flat_map(|(_, words)| words), alphabet)
+    }
+
+    /// Count all quadgrams inside the words of a wordlist while reading it, without keeping the words in memory
+    pub fn read_wordlist(reader: impl BufRead, alphabet: &Alphabet) -> io::Result<Self> {
+        let mut error = None;
+        let words =
+            read_words(reader, alphabet).map_while(|word| word.map_err(|e| error = Some(e)).ok());
+        let model = Self::from_words(words, alphabet);
+
+        match error {
+            Some(e) => Err(e),
+            None => Ok(model),
+        }
+    }
+
+    fn index(window: &[usize], letters: usize) -> usize {
+        window.iter().fold(0, |acc, &i| acc * letters + i)
+    }
+
+    /// Same as `fitness`, but for letters that are already converted to alphabet indices
+    pub fn fitness_indices(&self, indices: &[usize]) -> f64 {
+        indices
+            .windows(NGRAM_SIZE)
+            .map(|window| self.log_probs[Self::index(window, self.alphabet.len())])
+            .sum()
+    }
+
+    /// Sum of the log probabilities of all quadgrams in a word. Quadgrams with unknown letters count as unseen
+    pub fn fitness(&self, text: &str) -> f64 {
+        let indices = text
+            .chars()
+            .map(|c| self.alphabet.index(c).map(usize::from))
+            .collect::<Vec<_>>();
+
+        indices
+            .windows(NGRAM_SIZE)
+            .map(
+                |window| match window.iter().copied().collect::<Option<Vec<_>>>() {
+                    Some(window) => self.log_probs[Self::index(&window, self.alphabet.len())],
+                    None => self.floor,
+                },
+            )
+            .sum()
+    }
+
+    /// Log probability of a quadgram that was never seen
+    pub fn floor(&self) -> f64 {
+        self.floor
+    }
+}
+
+/// Letter bigrams including the start and end of words, which also tell short words apart that have no quadgrams
+pub struct BigramModel {
+    pub alphabet: Alphabet,
+    log_probs: Vec<f64>,
+    floor: f64,
+}
+impl BigramModel {
+    /// Count all bigrams inside of the given words and at their edges, weighted by how often each word occurs
+    pub fn from_words<S: AsRef<str>>(
+        words: impl IntoIterator<Item = (S, u64)>,
+        alphabet: &Alphabet,
+    ) -> Self {
+        // The last index is the edge of a word
+        let symbols = alphabet.len() + 1;
+        let mut counts = vec![0u64; symbols * symbols];
+        let mut total = 0;
+
+        for (word, count) in words {
+            for window in Self::indices(word.as_ref(), alphabet).windows(2) {
+                if let [Some(a), Some(b)] = window {
+                    counts[a * symbols + b] += count;
+                    total += count;
+                }
+            }
+        }
+
+        let total = total.max(1) as f64;
+        let floor = (0.01 / total).log10();
+        BigramModel {
+            alphabet: alphabet.clone(),
+            log_probs: counts
+                .into_iter()
+                .map(|count| match count {
+                    0 => floor,
+                    count => (count as f64 / total).log10(),
+                })
+                .collect(),
+            floor,
+        }
+    }
+
+    /// Letters of a word as indices, between the index of the edge
+    fn indices(word: &str, alphabet: &Alphabet) -> Vec<Option<usize>> {
+        let edge = Some(alphabet.len());
+        std::iter::once(edge)
+            .chain(word.chars().map(|c| alphabet.index(c).map(usize::from)))
+            .chain(std::iter::once(edge))
+            .collect()
+    }
+
+    /// Sum of the log probabilities of all bigrams in a word, including the edges. Unknown letters count as unseen
+    pub fn fitness(&self, word: &str) -> f64 {
+        let symbols = self.alphabet.len() + 1;
+        Self::indices(word, &self.alphabet)
+            .windows(2)
+            .map(|window| match window {
+                [Some(a), Some(b)] => self.log_probs[a * symbols + b],
+                _ => self.floor,
+            })
+            .sum()
+    }
+}
+
+/// Score plaintexts by how likely their words are, and how much their letters look like the language
+pub struct Scorer {
+    word_log_probs: HashMap<String, f64>,
+    word_floor: f64,
+    pub ngrams: NgramModel,
+    pub bigrams: BigramModel,
+}
+impl Scorer {
+    pub fn new(dictionary: &(impl PatternLookup + ?Sized), alphabet: &Alphabet) -> Self {
+        let words = dictionary
+            .patterns()
+            .flat_map(|(_, words)| words)
+            .collect::<Vec<_>>();
+
+        Self::from_counts(&words, alphabet)
+    }
+
+    /// Create a scorer from words with the number of times they occur
+    pub fn from_counts(words: &[(&str, u64)], alphabet: &Alphabet) -> Self {
+        let total = words.iter().map(|(_, count)| count).sum::<u64>().max(1) as f64;
+
+        Scorer {
+            word_log_probs: words
+                .iter()
+                .map(|&(word, count)| (word.to_string(), (count as f64 / total).log10()))
+                .collect(),
+            word_floor: (0.01 / total).log10(),
+            ngrams: NgramModel::from_words(words.iter().copied(), alphabet),
+            bigrams: BigramModel::from_words(words.iter().copied(), alphabet),
+        }
+    }
+
+    /// Log probability of all words in the plaintext plus the letter quadgram and bigram fitness.
+    /// Without counts in the wordlist all words are as likely, so bigrams still rank short words. Higher is better
+    pub fn score(&self, plaintext: &str) -> f64 {
+        plaintext
+            .split_whitespace()
+            .map(|word| {
+                self.word_log_probs
+                    .get(word)
+                    .copied()
+                    .unwrap_or(self.word_floor)
+                    + self.ngrams.fitness(word)
+                    + self.bigrams.fitness(word)
+            })
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::load_wordlist;
+
+    use super::*;
+
+    #[test]
+    fn scores_words_higher() {
+        let dictionary = load_wordlist(
+            &["some", "english", "text", "here"].join("\n"),
+            &Alphabet::default(),
+        );
+        let scorer = Scorer::new(&dictionary, &Alphabet::default());
+
+        assert!(scorer.score("some english text") > scorer.score("sove english text"));
+        assert!(scorer.score("some english text") > scorer.score("some ????ish text"));
+        assert!(scorer.ngrams.fitness("english") > scorer.ngrams.fitness("enghlis"));
+    }
+
+    #[test]
+    fn scores_short_words_by_bigrams() {
+        let alphabet = Alphabet::default();
+        let scorer = Scorer::new(&crate::builtin_dictionary(), &alphabet);
+
+        assert!(scorer.score("my") > scorer.score("mr"));
+        assert!(scorer.score("my") > scorer.score("mu"));
+        assert!(scorer.score("the") > scorer.score("thy"));
+        assert!(scorer.bigrams.fitness("th") > scorer.bigrams.fitness("ht"));
+    }
+
+    #[test]
+    fn scores_frequent_words_higher() {
+        let scorer = Scorer::from_counts(&[("the", 100), ("thy", 1)], &Alphabet::default());
+
+        assert!(scorer.score("the") > scorer.score("thy"));
+        assert_eq!(scorer.ngrams.fitness("the"), 0.0); // No quadgrams in short words
+    }
+
+    #[test]
+    fn reads_quadgrams_from_wordlist() {
+        let wordlist = "some\t3\nenglish\ntext here";
+        let alphabet = Alphabet::default();
+        let read = NgramModel::read_wordlist(wordlist.as_bytes(), &alphabet).unwrap();
+        let loaded = NgramModel::from_dictionary(&load_wordlist(wordlist, &alphabet), &alphabet);
+
+        for text in ["some english", "text here", "nothing"] {
+            assert_eq!(read.fitness(text), loaded.fitness(text));
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use std::{
+    collections::HashMap,
+    sync::{mpsc, Arc},
+};
+
+use crate::{
+    alphabet::Alphabet,
+    budget::Budget,
+    key::{Exclusions, Key, LetterSet, MAX_LETTERS},
+    normalize,
+    solve::{EncodedWord, Solution},
+    PatternLookup, Word,
+};
+
+/// Solver for ciphertexts without spaces, that searches for word boundaries together with the key
+pub struct SegmentSolver {
+    /// Ciphertext letters without any spaces
+    pub ciphertext: String,
+    /// All cipher words that can start at each position, longest first
+    pub segments: Vec<Vec<Word>>,
+    /// Letters that the cipher words and candidates consist of
+    pub alphabet: Alphabet,
+    /// Plaintext letters that cipher letters can't be
+    pub exclusions: Exclusions,
+    /// Limits that stop the search early, which can be shared with other searches
+    pub budget: Arc<Budget>,
+    allowed: [LetterSet; MAX_LETTERS],
+}
+impl SegmentSolver {
+    /// Find all possible cipher words in the ciphertext.
+    /// Returns an error if the ciphertext can't be split into words from the dictionary at all
+    pub fn new(
+        ciphertext: &str,
+        dictionary: &(impl PatternLookup + ?Sized),
+    ) -> Result<Self, String> {
+        let letters = ciphertext
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect::<Vec<_>>();
+        let max_length = (dictionary.patterns())
+            .map(|(pattern, _)| pattern.len())
+            .max()
+            .unwrap_or(0);
+
+        // Build from the end, so only segments that can be followed by more words are kept
+        let mut segments = vec![Vec::new(); letters.len()];
+        let mut reachable = vec![false; letters.len() + 1];
+        reachable[letters.len()] = true;
+
+        for start in (0..letters.len()).rev() {
+            for length in (1..=max_length.min(letters.len() - start)).rev() {
+                if !reachable[start + length] {
+                    continue;
+                }
+
+                let word = letters[start..start + length].iter().collect::<String>();
+                if let Some(candidates) = dictionary.words(&normalize(&word)) {
+                    segments[start].push(Word::new(&word, &candidates));
+                }
+            }
+            reachable[start] = !segments[start].is_empty();
+        }
+
+        if !reachable[0] {
+            return Err("Ciphertext can't be split into words from the dictionary".to_string());
+        }
+
+        Ok(SegmentSolver {
+            ciphertext: letters.into_iter().collect(),
+            segments,
+            alphabet: Alphabet::default(),
+            exclusions: Exclusions::default(),
+            budget: Arc::new(Budget::unlimited()),
+            allowed: [LetterSet::EMPTY; MAX_LETTERS],
+        })
+    }
+
+    pub fn solve(
+        &mut self,
+        starting_key: HashMap<char, char>,
+        tx: Option<&mpsc::Sender<Solution>>,
+    ) {
+        let Some(key) = Key::from_map(&starting_key, &self.alphabet, 1) else {
+            return;
+        };
+        self.allowed = self.exclusions.allowed(&self.alphabet);
+
+        let encoded = self
+            .segments
+            .iter()
+            .map(|words| {
+                words
+                    .iter()
+                    .map(|word| EncodedWord::new(word, &self.alphabet))
+                    .collect()
+            })
+            .collect::<Vec<_>>();
+        self.solve_recursive(&encoded, 0, &key, &mut Vec::new(), tx);
+    }
+
+    fn solve_recursive(
+        &self,
+        encoded: &[Vec<EncodedWord>],
+        position: usize,
+        key: &Key,
+        words: &mut Vec<String>,
+        tx: Option<&mpsc::Sender<Solution>>,
+    ) {
+        if !self.budget.visit() {
+            return;
+        }
+        if position >= encoded.len() {
+            // Solution found
+            if !self.budget.take_solution() {
+                return;
+            }
+            let mut solution = Solution::new(key.to_map(&self.alphabet));
+            solution.segments = words.to_owned();
+            solution.alphabet = self.alphabet.clone();
+            if let Some(tx) = tx {
+                tx.send(solution).unwrap();
+            }
+            return;
+        }
+
+        // Explore all words starting here, and all of their candidates
+        for word in &encoded[position] {
+            words.push(word.word.clone());
+            for plain in word
+                .candidates()
+                .filter(|plain| word.fits(plain, &self.allowed))
+            {
+                let mut next = *key;
+                if next.extend(&word.cipher, plain) {
+                    self.solve_recursive(encoded, position + word.cipher.len(), &next, words, tx);
+                }
+            }
+            words.pop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{alphabet::Alphabet, load_wordlist};
+
+    use super::*;
+
+    #[test]
+    fn can_solve_without_spaces() {
+        let wordlist = ["a", "few", "words", "here", "sword", "to", "test"].join("\n");
+        let dictionary = load_wordlist(&wordlist, &Alphabet::default());
+
+        let mut solver = SegmentSolver::new("xcbt tloap", &dictionary).unwrap();
+        assert_eq!(solver.ciphertext, "xcbttloap");
+
+        let (tx, rx) = mpsc::channel();
+        solver.solve(HashMap::new(), Some(&tx));
+        drop(tx);
+
+        let plaintexts = rx
+            .iter()
+            .map(|s| s.apply(&s.segments.join(" ")))
+            .collect::<Vec<_>>();
+        assert!(plaintexts.contains(&"a few words".to_string()));
+    }
+
+    #[test]
+    fn impossible_split() {
+        let dictionary = load_wordlist("few\nwords", &Alphabet::default());
+
+        assert!(SegmentSolver::new("xcbttlo", &dictionary).is_err());
+    }
+}
@@ -1,192 +1,563 @@
 use base64::{prelude::BASE64_STANDARD, Engine};
 use std::{
-    collections::{HashMap, HashSet},
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap},
     fmt::Display,
-    sync::mpsc,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc, Arc,
+    },
+    thread,
 };
 
-use crate::Word;
-
-fn intersect(a: HashSet<char>, b: HashSet<char>) -> HashSet<char> {
-    let mut result = HashSet::new();
+use crate::{
+    alphabet::Alphabet,
+    budget::Budget,
+    key::{Exclusions, Key, LetterSet, MAX_LETTERS},
+    Word,
+};
 
-    for i in a {
-        if b.contains(&i) {
-            result.insert(i);
+/// Cipher word with its candidates encoded as alphabet indices, for fast solving
+#[derive(Debug, Clone)]
+pub(crate) struct EncodedWord {
+    pub word: String,
+    pub cipher: Vec<u8>,
+    /// All candidates after each other, each with the same length as `cipher`
+    candidates: Vec<u8>,
+}
+impl EncodedWord {
+    pub fn new(word: &Word, alphabet: &Alphabet) -> Self {
+        EncodedWord {
+            word: word.word.clone(),
+            cipher: alphabet.encode_cipher(&word.word).unwrap_or_default(),
+            candidates: word
+                .candidates
+                .iter()
+                .filter_map(|candidate| alphabet.encode(candidate))
+                .flatten()
+                .collect(),
         }
     }
 
-    result
-}
+    pub fn candidates(&self) -> impl Iterator<Item = &[u8]> {
+        self.candidates.chunks_exact(self.cipher.len().max(1))
+    }
 
-/// Remove certain words from the candidates that are not possible
-pub fn prune(cipher_words: &mut [Word]) {
-    // Initialize with all possible letters
-    let mut pruner: HashMap<char, HashSet<char>> = HashMap::new();
-    for i in 'a'..='z' {
-        for j in 'a'..='z' {
-            pruner.entry(i).or_default().insert(j);
-        }
+    pub fn fits(&self, plain: &[u8], letters: &[LetterSet; MAX_LETTERS]) -> bool {
+        self.cipher
+            .iter()
+            .zip(plain)
+            .all(|(&c, &p)| letters[c as usize].contains(p))
     }
+}
 
-    // Remove letters that are not possible
-    for word in cipher_words.iter() {
-        for j in word.word.chars() {
-            pruner.entry(j).and_modify(|e| {
-                *e = intersect(e.clone(), word.letter_map.get(&j).unwrap().clone())
-            });
+/// A word that is not decided yet, with its candidates that still fit
+type OpenWord<'a> = (&'a EncodedWord, Vec<&'a [u8]>);
+
+/// Possible plaintext letters for every cipher letter, given the letters that are already in the key
+/// and the letters that are `allowed` at all
+fn key_letters(
+    key: &Key,
+    alphabet: &Alphabet,
+    allowed: &[LetterSet; MAX_LETTERS],
+) -> [LetterSet; MAX_LETTERS] {
+    let mut unused = LetterSet::all(alphabet.len());
+    for p in 0..alphabet.len() as u8 {
+        if key.is_full(p) {
+            unused.remove(p);
         }
     }
 
-    // Remove candidates that are not possible
-    for word in cipher_words.iter_mut() {
-        for j in 0..word.word.len() {
-            word.candidates.retain(|k| {
-                pruner
-                    .get(&word.word.chars().nth(j).unwrap())
-                    .unwrap()
-                    .contains(&k.chars().nth(j).unwrap())
-            });
+    let mut letters = [unused; MAX_LETTERS];
+    for c in 0..alphabet.cipher_letters().len() as u8 {
+        if let Some(p) = key.get(c) {
+            letters[c as usize] = LetterSet::single(p);
         }
+        letters[c as usize] &= allowed[c as usize];
     }
+    letters
 }
 
-pub fn order_by_possible_words(cipher_words: &mut [Word]) {
-    cipher_words.sort_by(|a, b| {
-        let a = a.word.len();
-        let b = b.word.len();
+/// Remove candidates that don't fit the possible letters, and remove letters that no candidate uses,
+/// repeating until nothing changes anymore. A plaintext letter that is the only option for as many cipher letters
+/// as it may have homophones is also removed from all other cipher letters (only one if the key is one-to-one).
+///
+/// If not `strict`, words may still be skipped as unknown, so they only lose candidates that don't fit.
+/// Returns `false` if some word or letter has no options left
+fn propagate(
+    letters: &mut [LetterSet; MAX_LETTERS],
+    words: &mut [OpenWord],
+    strict: bool,
+    max_homophones: usize,
+) -> bool {
+    for (word, candidates) in words.iter_mut() {
+        candidates.retain(|plain| word.fits(plain, letters));
+    }
+    if !strict {
+        return true;
+    }
 
-        b.cmp(&a)
-    });
-}
+    // Only letters in the ciphertext need a plaintext letter
+    let mut active = LetterSet::EMPTY;
+    for (word, _) in words.iter() {
+        for &c in &word.cipher {
+            active.insert(c);
+        }
+    }
 
-fn is_consistent(map: &HashMap<char, char>) -> bool {
-    let mut counter: HashMap<char, char> = HashMap::new();
+    loop {
+        let mut narrowed = *letters;
 
-    for (&first, &second) in map.iter() {
-        counter.insert(second, first);
-    }
+        // Letters can only be what one of the candidates has there
+        let mut word_letters = [LetterSet::EMPTY; MAX_LETTERS];
+        for (word, candidates) in words.iter() {
+            for plain in candidates {
+                for (&c, &p) in word.cipher.iter().zip(*plain) {
+                    word_letters[c as usize].insert(p);
+                }
+            }
+            for &c in &word.cipher {
+                narrowed[c as usize] &= word_letters[c as usize];
+            }
+            for &c in &word.cipher {
+                word_letters[c as usize] = LetterSet::EMPTY;
+            }
+        }
 
-    map.len() == counter.len()
-}
+        // Letters that are decided can't be used by any other letter, once they have all their homophones
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for c in active.iter() {
+                if narrowed[c as usize].len() != 1 {
+                    continue;
+                }
+                let decided = narrowed[c as usize];
+                let mut same = LetterSet::EMPTY;
+                for other in active.iter() {
+                    if narrowed[other as usize] == decided {
+                        same.insert(other);
+                    }
+                }
+                if same.len() > max_homophones {
+                    return false;
+                } else if same.len() < max_homophones {
+                    continue;
+                }
+
+                for other in active.iter().filter(|&other| !same.contains(other)) {
+                    if (narrowed[other as usize] & decided) != LetterSet::EMPTY {
+                        narrowed[other as usize].0 &= !decided.0;
+                        changed = true;
+                    }
+                }
+            }
+        }
 
-fn apply_map(cipher: &str, plain: &str, map: &HashMap<char, char>) -> String {
-    let mut result = String::new();
+        if active.iter().any(|c| narrowed[c as usize].is_empty()) {
+            return false;
+        }
+        if narrowed == *letters {
+            return true;
+        }
+        *letters = narrowed;
 
-    for (i, c) in cipher.chars().enumerate() {
-        let c = *map.get(&c).unwrap_or(&plain.chars().nth(i).unwrap());
-        result.push(c);
+        for (word, candidates) in words.iter_mut() {
+            candidates.retain(|plain| word.fits(plain, letters));
+            if candidates.is_empty() {
+                return false;
+            }
+        }
     }
-
-    result
 }
-fn update_map(cipher: &str, plain: &str, map: &HashMap<char, char>) -> HashMap<char, char> {
-    let mut map = map.to_owned();
 
-    for (i, c) in cipher.chars().enumerate() {
-        map.entry(c)
-            .or_insert_with(|| plain.chars().nth(i).unwrap());
+/// Remove certain words from the candidates that are not possible, also using the letters that are excluded
+pub fn prune(
+    cipher_words: &mut [Word],
+    alphabet: &Alphabet,
+    max_homophones: usize,
+    exclusions: &Exclusions,
+) {
+    // Unknown words don't give any information
+    let encoded = cipher_words
+        .iter()
+        .filter(|w| !w.is_unknown())
+        .map(|word| EncodedWord::new(word, alphabet))
+        .collect::<Vec<_>>();
+    let mut words = encoded
+        .iter()
+        .map(|word| (word, word.candidates().collect()))
+        .collect::<Vec<_>>();
+
+    // Initialize with all possible letters
+    let mut letters = exclusions.allowed(alphabet);
+    if !propagate(&mut letters, &mut words, true, max_homophones) {
+        letters = [LetterSet::EMPTY; MAX_LETTERS];
     }
 
-    map
+    // Remove candidates that are not possible
+    for word in cipher_words.iter_mut() {
+        let Some(cipher) = alphabet.encode_cipher(&word.word) else {
+            continue;
+        };
+        word.candidates.retain(|candidate| {
+            alphabet.encode(candidate).is_some_and(|plain| {
+                plain.len() == cipher.len()
+                    && cipher
+                        .iter()
+                        .zip(plain)
+                        .all(|(&c, p)| letters[c as usize].contains(p))
+            })
+        });
+    }
 }
 
 pub struct Solver {
     pub cipher_words: Vec<Word>,
+    /// Maximum number of words that may be skipped as unknown
+    pub max_unknown: usize,
+    /// Number of threads to split the candidates of the first word over
+    pub threads: usize,
+    /// Send solutions in the same order as with a single thread, instead of as soon as they are found
+    pub ordered: bool,
+    /// Letters that the cipher words and candidates consist of
+    pub alphabet: Alphabet,
+    /// Most cipher letters that may map to the same plaintext letter, more than 1 for homophonic ciphers
+    pub max_homophones: usize,
+    /// Plaintext letters that cipher letters can't be
+    pub exclusions: Exclusions,
+    /// Limits that stop the search early, which can be shared with other searches
+    pub budget: Arc<Budget>,
+    encoded: Vec<EncodedWord>,
+    allowed: [LetterSet; MAX_LETTERS],
 }
 impl Solver {
     pub fn new(cipher_words: &Vec<Word>) -> Self {
         Solver {
             cipher_words: cipher_words.to_owned(),
+            max_unknown: 0,
+            threads: 1,
+            ordered: false,
+            alphabet: Alphabet::default(),
+            max_homophones: 1,
+            exclusions: Exclusions::default(),
+            budget: Arc::new(Budget::unlimited()),
+            encoded: Vec::new(),
+            allowed: [LetterSet::EMPTY; MAX_LETTERS],
         }
     }
 
     pub fn solve(
         &mut self,
-        mut starting_key: HashMap<char, char>,
+        starting_key: HashMap<char, char>,
         tx: Option<&mpsc::Sender<Solution>>,
     ) {
-        self.solve_recursive(0, &mut starting_key, tx);
+        let Some(key) = Key::from_map(&starting_key, &self.alphabet, self.max_homophones) else {
+            return;
+        };
+
+        self.allowed = self.exclusions.allowed(&self.alphabet);
+
+        // The same cipher word always has the same plaintext, so it only needs to be searched once
+        self.encoded = Vec::new();
+        for word in &self.cipher_words {
+            if !self.encoded.iter().any(|w| w.word == word.word) {
+                self.encoded.push(EncodedWord::new(word, &self.alphabet));
+            }
+        }
+
+        let words = self
+            .encoded
+            .iter()
+            .map(|word| (word, word.candidates().collect()))
+            .collect::<Vec<_>>();
+        if self.threads > 1 && !words.is_empty() {
+            self.solve_parallel(&key, words, tx);
+        } else {
+            self.solve_recursive(&key, &words, &mut Vec::new(), tx, &self.budget);
+        }
     }
 
-    fn solve_recursive(
-        &mut self,
-        depth: usize,
-        map: &mut HashMap<char, char>,
-        tx: Option<&mpsc::Sender<Solution>>,
-    ) {
-        if is_consistent(map) {
-            if depth >= self.cipher_words.len() {
-                // Solution found
-                let solution = Solution::new(map.to_owned());
-                if let Some(tx) = tx {
-                    tx.send(solution).unwrap();
-                }
-            } else {
-                // Explore all candidates
-                for i in self.cipher_words[depth].candidates.to_owned().iter() {
-                    if &apply_map(&self.cipher_words[depth].word, i, map) == i {
-                        self.solve_recursive(
-                            depth + 1,
-                            &mut update_map(&self.cipher_words[depth].word, i, map),
-                            tx,
-                        );
+    /// Narrow down the open words with the key, and take out the most constrained one to explore next.
+    /// Returns `None` if there is no solution in this branch
+    fn next_word<'a>(
+        &self,
+        key: &Key,
+        words: &[OpenWord<'a>],
+        unknown: &[String],
+    ) -> Option<(Vec<OpenWord<'a>>, Option<OpenWord<'a>>)> {
+        let mut words = words.to_vec();
+        // Words can only rule out letters for each other if none of them may be skipped anymore
+        let strict = unknown.len() >= self.max_unknown;
+        let mut letters = key_letters(key, &self.alphabet, &self.allowed);
+        if !propagate(&mut letters, &mut words, strict, self.max_homophones) {
+            return None;
+        }
+
+        // Fewest candidates left, or the longest word if they have the same number, because it decides more letters
+        let position =
+            (0..words.len()).min_by_key(|&i| (words[i].1.len(), Reverse(words[i].0.cipher.len())));
+        let word = position.map(|i| words.swap_remove(i));
+
+        Some((words, word))
+    }
+
+    /// Give every thread the next unexplored candidate of the most constrained word, until all are done
+    fn solve_parallel(&self, key: &Key, words: Vec<OpenWord>, tx: Option<&mpsc::Sender<Solution>>) {
+        let Some((words, Some((word, candidates)))) = self.next_word(key, &words, &[]) else {
+            return;
+        };
+        let next_candidate = AtomicUsize::new(0);
+        let found = AtomicBool::new(false);
+
+        thread::scope(|scope| {
+            // Solutions of every branch by candidate index, only used when ordered
+            let (branch_tx, branch_rx) = mpsc::channel::<(usize, mpsc::Receiver<Solution>)>();
+
+            for _ in 0..self.threads {
+                let branch_tx = branch_tx.clone();
+                let tx = tx.cloned();
+                let (words, candidates, next_candidate, found) =
+                    (&words, &candidates, &next_candidate, &found);
+
+                scope.spawn(move || loop {
+                    let i = next_candidate.fetch_add(1, Ordering::Relaxed);
+                    let Some(plain) = candidates.get(i) else {
+                        break;
+                    };
+                    let mut next = *key;
+                    let fits = next.extend(&word.cipher, plain);
+
+                    let branch_found = if self.ordered {
+                        // Solutions are counted again when they are passed on in order, so the same ones are kept every time.
+                        // A branch never needs more than the maximum, so it stops there
+                        let (solution_tx, solution_rx) = mpsc::channel();
+                        branch_tx.send((i, solution_rx)).unwrap();
+                        let branch = Budget::new(self.budget.max_solutions, None, None);
+                        fits && self.solve_recursive(
+                            &next,
+                            words,
+                            &mut Vec::new(),
+                            Some(&solution_tx),
+                            &branch,
+                        )
+                    } else {
+                        fits && self.solve_recursive(
+                            &next,
+                            words,
+                            &mut Vec::new(),
+                            tx.as_ref(),
+                            &self.budget,
+                        )
+                    };
+                    if branch_found {
+                        found.store(true, Ordering::Relaxed);
                     }
+                });
+            }
+            drop(branch_tx);
+
+            // Pass on solutions in order of their candidate. The first unfinished branch is passed on while it searches,
+            // and branches that are further along wait in their channel
+            let mut branches = BTreeMap::new();
+            let mut next_branch = 0;
+            for (i, branch) in branch_rx {
+                branches.insert(i, branch);
+                while let Some(branch) = branches.remove(&next_branch) {
+                    for solution in branch {
+                        // Stops the other threads once there are enough solutions
+                        if self.budget.take_solution() {
+                            if let Some(tx) = tx {
+                                tx.send(solution).unwrap();
+                            }
+                        }
+                    }
+                    next_branch += 1;
                 }
             }
+        });
+
+        // Only skip the word as unknown if none of its candidates lead to a solution
+        if !found.into_inner() && self.max_unknown > 0 {
+            self.solve_recursive(key, &words, &mut vec![word.word.clone()], tx, &self.budget);
         }
     }
+
+    /// Returns `true` if any solutions were found in this branch.
+    /// Solutions are counted by `solutions`, which is the budget of the solver unless whoever receives them counts them
+    fn solve_recursive(
+        &self,
+        key: &Key,
+        words: &[OpenWord],
+        unknown: &mut Vec<String>,
+        tx: Option<&mpsc::Sender<Solution>>,
+        solutions: &Budget,
+    ) -> bool {
+        if !self.budget.visit() || solutions.stopped().is_some() {
+            return false;
+        }
+        let Some((words, next)) = self.next_word(key, words, unknown) else {
+            return false;
+        };
+        let Some((word, candidates)) = next else {
+            // Solution found
+            if !solutions.take_solution() {
+                return false;
+            }
+            let mut solution = Solution::new(key.to_map(&self.alphabet));
+            solution.unknown = unknown.to_owned();
+            solution.alphabet = self.alphabet.clone();
+            if let Some(tx) = tx {
+                tx.send(solution).unwrap();
+            }
+            return true;
+        };
+
+        // Explore all candidates
+        let mut found = false;
+        for plain in candidates {
+            let mut next = *key;
+            if next.extend(&word.cipher, plain) {
+                found |= self.solve_recursive(&next, &words, unknown, tx, solutions);
+            }
+        }
+
+        // Only skip the word as unknown if none of its candidates lead to a solution
+        if !found && unknown.len() < self.max_unknown {
+            unknown.push(word.word.clone());
+            found = self.solve_recursive(key, &words, unknown, tx, solutions);
+            unknown.pop();
+        }
+
+        found
+    }
 }
 
 pub struct Solution {
     pub key: HashMap<char, char>,
+    /// Cipher words that were skipped as unknown
+    pub unknown: Vec<String>,
+    /// How likely the plaintext is, if it was scored (higher is better)
+    pub score: Option<f64>,
+    /// Cipher words found in a ciphertext without spaces
+    pub segments: Vec<String>,
+    /// Letters that the key maps, in the order they are shown
+    pub alphabet: Alphabet,
+    /// Where the crib was placed in the ciphertext to find this solution, if a crib was given
+    pub crib: Option<String>,
+    /// Positions in the printed plaintext of letters that the key doesn't know, set when it is made
+    pub unknown_positions: Vec<usize>,
 }
 impl Solution {
     pub fn new(key: HashMap<char, char>) -> Self {
-        Solution { key }
+        Solution {
+            key,
+            unknown: Vec::new(),
+            score: None,
+            segments: Vec::new(),
+            alphabet: Alphabet::default(),
+            crib: None,
+            unknown_positions: Vec::new(),
+        }
     }
 
     /// Fill unknowns in the key with unused letters
     pub fn fill_key(&mut self) {
-        let mut unused: Vec<char> = ('a'..='z').rev().collect();
+        let mut unused: Vec<char> = self.alphabet.letters().iter().rev().copied().collect();
         // Filter used letters
         for value in self.key.values() {
             unused.retain(|x| x != value);
         }
 
         // Fill unknown letters with unused letters
-        for c in 'a'..='z' {
-            self.key.entry(c).or_insert_with(|| unused.pop().unwrap());
+        for &c in self.alphabet.cipher_letters() {
+            if self.key.contains_key(&c) {
+                continue;
+            }
+            // With more cipher symbols than letters, not all of them can get one
+            let Some(letter) = unused.pop() else {
+                break;
+            };
+            self.key.insert(c, letter);
         }
     }
 
     pub fn apply(&self, ciphertext: &str) -> String {
+        self.apply_with_unknown(ciphertext).0
+    }
+
+    /// Apply the key, and also return the positions (in characters) of the letters it doesn't know
+    pub fn apply_with_unknown(&self, ciphertext: &str) -> (String, Vec<usize>) {
+        let mut result = String::new();
+        let mut unknown = Vec::new();
+
+        for (i, c) in ciphertext.chars().enumerate() {
+            match self.key.get(&c) {
+                Some(&plain) => result.push(plain),
+                // Show unknown letters as '?', but keep punctuation
+                None if c.is_alphabetic() || self.alphabet.cipher_letters().contains(&c) => {
+                    result.push('?');
+                    unknown.push(i);
+                }
+                None => result.push(c),
+            }
+        }
+
+        (result, unknown)
+    }
+
+    /// Apply the key to the original ciphertext, keeping the case of every letter and leaving
+    /// everything else like punctuation, digits and line breaks as it was
+    pub fn apply_formatted(&self, ciphertext: &str) -> String {
+        self.apply_formatted_with_unknown(ciphertext).0
+    }
+
+    /// Apply the key like `apply_formatted`, and also return the positions (in characters) of the letters it doesn't know.
+    /// A '?' that was already in the ciphertext is not one of them
+    pub fn apply_formatted_with_unknown(&self, ciphertext: &str) -> (String, Vec<usize>) {
         let mut result = String::new();
+        let mut unknown = Vec::new();
+        let mut length = 0;
 
         for c in ciphertext.chars() {
-            // Show unknown letters as '?', but keep punctuation
-            result.push(*self.key.get(&c).unwrap_or_else(|| {
-                if c.is_alphabetic() {
-                    &'?'
+            // Letters were folded into the alphabet when cleaning, like 'É' into "e", so they are solved as those
+            let Some(letters) = self.alphabet.fold(c) else {
+                result.push(c);
+                length += 1;
+                continue;
+            };
+
+            for letter in letters.chars() {
+                let plain = self.key.get(&letter).copied().unwrap_or_else(|| {
+                    unknown.push(length);
+                    '?'
+                });
+                let start = result.len();
+                if c.is_uppercase() {
+                    result.extend(plain.to_uppercase());
                 } else {
-                    &c
+                    result.push(plain);
                 }
-            }));
+                length += result[start..].chars().count();
+            }
         }
 
-        result
+        (result, unknown)
     }
 
     pub fn format_hyperlink(&self, ciphertext: &str) -> String {
         let ciphertext = BASE64_STANDARD.encode(ciphertext);
-        let url = format!("https://gchq.github.io/CyberChef/#recipe=Substitute('ABCDEFGHIJKLMNOPQRSTUVWXYZ','{self}',true)&input={ciphertext}");
+        let alphabet = self.alphabet.to_string().to_uppercase();
+        let url = format!("https://gchq.github.io/CyberChef/#recipe=Substitute('{alphabet}','{self}',true)&input={ciphertext}");
         format!("\x1b]8;;{url}\x1b\\{self}\x1b]8;;\x1b\\")
     }
 }
 impl Display for Solution {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        ('a'..='z')
-            .map(|c| *self.key.get(&c).unwrap_or(&'?'))
+        self.alphabet
+            .cipher_letters()
+            .iter()
+            .map(|c| *self.key.get(c).unwrap_or(&'?'))
             .collect::<String>()
             .fmt(f)
     }
@@ -194,7 +565,7 @@ impl Display for Solution {
 
 #[cfg(test)]
 mod tests {
-    use crate::{input::input_to_words, load_wordlist};
+    use crate::{alphabet::Alphabet, budget::Stop, input::input_to_words, load_wordlist};
 
     use super::*;
 
@@ -206,9 +577,9 @@ mod tests {
             "now", "all", "words", "should", "be", "good",
         ]
         .join("\n");
-        let dictionary = load_wordlist(&wordlist);
+        let dictionary = load_wordlist(&wordlist, &Alphabet::default());
 
-        let cipher_words = input_to_words(ciphertext, &dictionary).unwrap();
+        let cipher_words = input_to_words(ciphertext, &dictionary, 0, 1).unwrap();
         let mut solver = Solver::new(&cipher_words);
 
         let (tx, rx) = mpsc::channel();
@@ -218,4 +589,260 @@ mod tests {
         let plaintext = solution.apply(ciphertext);
         assert_eq!(plaintext, "a few words");
     }
+
+    #[test]
+    fn can_solve_other_alphabet() {
+        let alphabet = Alphabet::preset("russian").unwrap();
+        let dictionary = load_wordlist("привет\nмир\nдом", &alphabet);
+
+        // Every letter shifted by one
+        let ciphertext = "рсйгёу нйс";
+        let mut cipher_words = input_to_words(ciphertext, &dictionary, 0, 1).unwrap();
+        prune(&mut cipher_words, &alphabet, 1, &Exclusions::default());
+        let mut solver = Solver::new(&cipher_words);
+        solver.alphabet = alphabet;
+
+        let (tx, rx) = mpsc::channel();
+        solver.solve(HashMap::new(), Some(&tx));
+        drop(tx);
+
+        let solutions = rx.iter().collect::<Vec<_>>();
+        assert_eq!(solutions.len(), 1);
+        assert_eq!(solutions[0].apply(ciphertext), "привет мир");
+        assert_eq!(
+            solutions[0].to_string(),
+            "???в??е???и???м??пр?т????????????"
+        );
+    }
+
+    #[test]
+    fn apply_formatted() {
+        let solution = Solution::new(
+            [
+                ('x', 'a'),
+                ('c', 'f'),
+                ('b', 'e'),
+                ('t', 'w'),
+                ('l', 'o'),
+                ('o', 'r'),
+            ]
+            .into(),
+        );
+
+        assert_eq!(
+            solution.apply_formatted("X cbt, 2 TLOap!\n\nCbt?"),
+            "A few, 2 WOR??!\n\nFew?"
+        );
+        assert_eq!(solution.apply_formatted("Çbt"), "Few");
+        assert_eq!(
+            solution.apply_formatted_with_unknown("Ap? Cbt?"),
+            ("??? Few?".to_string(), vec![0, 1])
+        );
+    }
+
+    #[test]
+    fn prune_propagates() {
+        let dictionary = load_wordlist(&["it", "at", "in", "on"].join("\n"), &Alphabet::default());
+        let mut cipher_words = input_to_words("ab cb", &dictionary, 0, 1).unwrap();
+        cipher_words[0].candidates.retain(|w| w == "it");
+
+        // 'b' must be 't', so "cb" can only be "at" because 'i' is already used by 'a'
+        prune(
+            &mut cipher_words,
+            &Alphabet::default(),
+            1,
+            &Exclusions::default(),
+        );
+        assert_eq!(cipher_words[1].candidates, vec!["at"]);
+
+        // Nothing is left when there is no solution
+        let mut cipher_words = input_to_words("ab cb", &dictionary, 0, 1).unwrap();
+        cipher_words[0].candidates.retain(|w| w == "it");
+        cipher_words[1].candidates.retain(|w| w == "it");
+        prune(
+            &mut cipher_words,
+            &Alphabet::default(),
+            1,
+            &Exclusions::default(),
+        );
+        assert!(cipher_words.iter().all(|w| w.candidates.is_empty()));
+    }
+
+    #[test]
+    fn merges_duplicate_words() {
+        let ciphertext = "x cbt tloap cbt";
+        let dictionary = load_wordlist(
+            &["a", "few", "words", "now"].join("\n"),
+            &Alphabet::default(),
+        );
+
+        let cipher_words = input_to_words(ciphertext, &dictionary, 0, 1).unwrap();
+        let mut solver = Solver::new(&cipher_words);
+
+        let (tx, rx) = mpsc::channel();
+        solver.solve(HashMap::new(), Some(&tx));
+        drop(tx);
+
+        assert_eq!(solver.encoded.len(), 3);
+        let plaintexts = rx.iter().map(|s| s.apply(ciphertext)).collect::<Vec<_>>();
+        assert_eq!(plaintexts, vec!["a few words few"]);
+    }
+
+    #[test]
+    fn can_solve_parallel() {
+        let ciphertext = "emd tcxd ec";
+        let dictionary = load_wordlist(
+            include_str!("../wordlist/english.txt"),
+            &Alphabet::default(),
+        );
+        let cipher_words = input_to_words(ciphertext, &dictionary, 0, 1).unwrap();
+
+        let solve_limited = |threads, ordered, max_solutions| {
+            let mut solver = Solver::new(&cipher_words);
+            solver.threads = threads;
+            solver.ordered = ordered;
+            solver.budget = Arc::new(Budget::new(max_solutions, None, None));
+
+            let (tx, rx) = mpsc::channel();
+            solver.solve(HashMap::new(), Some(&tx));
+            drop(tx);
+            rx.iter().map(|s| s.apply(ciphertext)).collect::<Vec<_>>()
+        };
+        let solve = |threads, ordered| solve_limited(threads, ordered, None);
+
+        let expected = solve(1, false);
+        assert!(expected.contains(&"the some to".to_string()));
+        assert_eq!(solve(4, true), expected);
+        // The first solutions in order, no matter which thread finds them first
+        for _ in 0..5 {
+            assert_eq!(solve_limited(4, true, Some(3)), expected[..3]);
+        }
+
+        let mut unordered = solve(4, false);
+        unordered.sort();
+        let mut expected = expected;
+        expected.sort();
+        assert_eq!(unordered, expected);
+    }
+
+    #[test]
+    fn solves_frequent_words_first() {
+        let dictionary = load_wordlist("thy\t5\nthe\t100\nand\t50", &Alphabet::default());
+
+        let cipher_words = input_to_words("xyz", &dictionary, 0, 1).unwrap();
+        let mut solver = Solver::new(&cipher_words);
+
+        let (tx, rx) = mpsc::channel();
+        solver.solve(HashMap::new(), Some(&tx));
+        drop(tx);
+
+        let plaintexts = rx.iter().map(|s| s.apply("xyz")).collect::<Vec<_>>();
+        assert_eq!(plaintexts, vec!["the", "and", "thy"]);
+    }
+
+    #[test]
+    fn can_solve_homophonic() {
+        // 'e' is both 'b' and 'c'
+        let ciphertext = "abc dfb dgcb";
+        let dictionary = load_wordlist("see\nsea\nthe\ntree\nthree", &Alphabet::default());
+        assert!(input_to_words(ciphertext, &dictionary, 0, 1).is_err());
+
+        let mut cipher_words = input_to_words(ciphertext, &dictionary, 0, 2).unwrap();
+        prune(
+            &mut cipher_words,
+            &Alphabet::default(),
+            2,
+            &Exclusions::default(),
+        );
+        let mut solver = Solver::new(&cipher_words);
+        solver.max_homophones = 2;
+
+        let (tx, rx) = mpsc::channel();
+        solver.solve(HashMap::new(), Some(&tx));
+        drop(tx);
+
+        let plaintexts = rx
+            .iter()
+            .map(|solution| solution.apply(ciphertext))
+            .collect::<Vec<_>>();
+        assert!(plaintexts.contains(&"see the tree".to_string()));
+    }
+
+    #[test]
+    fn solves_with_exclusions() {
+        let dictionary = load_wordlist("the\nand\nfor", &Alphabet::default());
+        let exclusions = Exclusions {
+            pairs: vec![('b', 'n')],
+            absent: vec!['t'],
+        };
+
+        let mut cipher_words = input_to_words("abc", &dictionary, 0, 1).unwrap();
+        prune(&mut cipher_words, &Alphabet::default(), 1, &exclusions);
+        assert_eq!(cipher_words[0].candidates, vec!["for"]);
+
+        let cipher_words = input_to_words("abc", &dictionary, 0, 1).unwrap();
+        let mut solver = Solver::new(&cipher_words);
+        solver.exclusions = exclusions;
+
+        let (tx, rx) = mpsc::channel();
+        solver.solve(HashMap::new(), Some(&tx));
+        drop(tx);
+
+        let plaintexts = rx.iter().map(|s| s.apply("abc")).collect::<Vec<_>>();
+        assert_eq!(plaintexts, vec!["for"]);
+    }
+
+    #[test]
+    fn stops_at_budget() {
+        let dictionary = load_wordlist("the\nand\nfor\nyou\nwas", &Alphabet::default());
+        let cipher_words = input_to_words("abc", &dictionary, 0, 1).unwrap();
+
+        for threads in [1, 4] {
+            let mut solver = Solver::new(&cipher_words);
+            solver.threads = threads;
+            solver.budget = Arc::new(Budget::new(Some(2), None, None));
+
+            let (tx, rx) = mpsc::channel();
+            solver.solve(HashMap::new(), Some(&tx));
+            drop(tx);
+            assert_eq!(rx.iter().count(), 2);
+            assert_eq!(solver.budget.stopped(), Some(Stop::Solutions));
+        }
+
+        let mut solver = Solver::new(&cipher_words);
+        solver.budget = Arc::new(Budget::new(None, None, Some(1)));
+        let (tx, rx) = mpsc::channel();
+        solver.solve(HashMap::new(), Some(&tx));
+        drop(tx);
+        assert_eq!(rx.iter().count(), 0);
+        assert_eq!(solver.budget.stopped(), Some(Stop::Nodes));
+
+        // Without limits, the search is complete
+        let mut solver = Solver::new(&cipher_words);
+        let (tx, rx) = mpsc::channel();
+        solver.solve(HashMap::new(), Some(&tx));
+        drop(tx);
+        assert_eq!(rx.iter().count(), 5);
+        assert_eq!(solver.budget.stopped(), None);
+    }
+
+    #[test]
+    fn can_solve_with_unknown() {
+        let ciphertext = "x cbt mlka tloap";
+        let wordlist = ["a", "few", "words", "here"].join("\n");
+        let dictionary = load_wordlist(&wordlist, &Alphabet::default());
+
+        let cipher_words = input_to_words(ciphertext, &dictionary, 1, 1).unwrap();
+        let mut solver = Solver::new(&cipher_words);
+        solver.max_unknown = 1;
+
+        let (tx, rx) = mpsc::channel();
+        solver.solve(HashMap::new(), Some(&tx));
+        drop(tx);
+
+        let solutions = rx.iter().collect::<Vec<_>>();
+        assert_eq!(solutions.len(), 1);
+        assert_eq!(solutions[0].unknown, vec!["mlka"]);
+        assert_eq!(solutions[0].apply(ciphertext), "a few ?o?d words");
+    }
 }
@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+
+use crate::{
+    alphabet::Alphabet,
+    input::{homophones_full, split_exclusions, too_many_homophones},
+    key::{Exclusions, MAX_LETTERS},
+};
+
+/// Cipher symbols are stored as characters from the Unicode private use area, starting here
+const FIRST_SYMBOL: u32 = 0xE000;
+
+/// Cipher symbols of a ciphertext that is not written in letters, like a symbol cipher or numbers ("12-5-7 3-1").
+/// Every different symbol is stored as one character internally, so it can be solved just like letters
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbols {
+    /// Original text of every symbol, in order of first appearance
+    pub tokens: Vec<String>,
+    /// Put between the symbols of a word when showing it, so they can be told apart
+    delimiter: String,
+}
+impl Symbols {
+    /// Split the ciphertext into words on whitespace, and every word into symbols.
+    /// Every character is a symbol, or every part between delimiters if a delimiter is given.
+    /// Returns the symbols and the ciphertext written in their internal characters, ready to solve
+    ///
+    /// ```rust
+    /// use sub_solver::symbols::Symbols;
+    ///
+    /// let (symbols, ciphertext) = Symbols::tokenize("12-5-7 3-12", Some("-")).unwrap();
+    /// assert_eq!(symbols.tokens, vec!["12", "5", "7", "3"]);
+    /// assert_eq!(symbols.decode(&ciphertext), "12-5-7 3-12");
+    /// ```
+    pub fn tokenize(input: &str, delimiter: Option<&str>) -> Result<(Self, String), String> {
+        let mut symbols = Symbols {
+            tokens: Vec::new(),
+            delimiter: delimiter.unwrap_or_default().to_string(),
+        };
+
+        let mut words = Vec::new();
+        for word in input.split_whitespace() {
+            let tokens = match delimiter {
+                Some(delimiter) => word
+                    .split(delimiter)
+                    .filter(|token| !token.is_empty())
+                    .map(str::to_string)
+                    .collect::<Vec<_>>(),
+                None => word.chars().map(String::from).collect(),
+            };
+
+            let mut encoded = String::new();
+            for token in tokens {
+                let id = match symbols.tokens.iter().position(|t| *t == token) {
+                    Some(id) => id,
+                    None => {
+                        symbols.tokens.push(token);
+                        symbols.tokens.len() - 1
+                    }
+                };
+                encoded.push(symbol_char(id));
+            }
+            if !encoded.is_empty() {
+                words.push(encoded);
+            }
+        }
+
+        if symbols.tokens.len() > MAX_LETTERS {
+            return Err(format!(
+                "Ciphertext has {} different symbols, but at most {MAX_LETTERS} are supported",
+                symbols.tokens.len()
+            ));
+        }
+
+        Ok((symbols, words.join(" ")))
+    }
+
+    /// Alphabet with these symbols as the cipher letters, and the letters of `alphabet` as plaintext letters
+    pub fn alphabet(&self, alphabet: &Alphabet) -> Alphabet {
+        alphabet.with_symbols((0..self.tokens.len()).map(symbol_char).collect())
+    }
+
+    /// Original text of the symbol stored as a character
+    pub fn token(&self, c: char) -> Option<&str> {
+        let id = (c as u32).checked_sub(FIRST_SYMBOL)?;
+        self.tokens.get(id as usize).map(String::as_str)
+    }
+
+    /// Convert internal characters in a text back to the original symbols, with the delimiter between them
+    pub fn decode(&self, text: &str) -> String {
+        let mut result = String::new();
+        let mut previous_symbol = false;
+
+        for c in text.chars() {
+            match self.token(c) {
+                Some(token) => {
+                    if previous_symbol {
+                        result += &self.delimiter;
+                    }
+                    result += token;
+                    previous_symbol = true;
+                }
+                None => {
+                    result.push(c);
+                    previous_symbol = false;
+                }
+            }
+        }
+
+        result
+    }
+
+    /// Show a key as a table of every symbol with its letter, or '?' if unknown (example: "12:l,5:e,7:?").
+    /// This is the same format as `parse_key` accepts
+    pub fn format_key(&self, key: &HashMap<char, char>) -> String {
+        self.tokens
+            .iter()
+            .enumerate()
+            .map(|(id, token)| {
+                let letter = key.get(&symbol_char(id)).unwrap_or(&'?');
+                format!("{token}:{letter}")
+            })
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    /// Parse a key of symbols and letters (example: "12:l,5:e"), where letters may be '?' if unknown.
+    /// Like other keys, it can exclude letters with "12!e" and "!z", and map up to `max_homophones` symbols to the same letter
+    pub fn parse_key(
+        &self,
+        key: &str,
+        alphabet: &Alphabet,
+        max_homophones: usize,
+    ) -> Result<(HashMap<char, char>, Exclusions), String> {
+        let (key, exclusions) = split_exclusions(key, alphabet, |token| self.symbol(token))?;
+        let mut result = HashMap::new();
+
+        for pair in key.split(',').filter(|pair| !pair.is_empty()) {
+            let Some((token, letter)) = pair.rsplit_once(':') else {
+                return Err(format!(
+                    "Invalid key pair: {pair:?} (should be \"symbol:letter\")"
+                ));
+            };
+            let c = self.symbol(token)?;
+
+            let mut letters = letter.chars();
+            let letter = match (letters.next(), letters.next()) {
+                (Some('?'), None) => continue,
+                (Some(letter), None) if alphabet.contains(letter) => letter,
+                _ => {
+                    return Err(format!(
+                        "Invalid key character: {letter:?} (should be in lowercase alphabet)"
+                    ))
+                }
+            };
+
+            if result.contains_key(&c) {
+                return Err(format!("Duplicate key symbol: {token:?}"));
+            }
+            if let Some(dup_key) = homophones_full(&result, letter, max_homophones) {
+                let dup_token = self.token(dup_key).unwrap_or_default();
+                return Err(too_many_homophones(
+                    letter,
+                    dup_token,
+                    token,
+                    max_homophones,
+                ));
+            }
+            if exclusions.excludes(c, letter) {
+                return Err(format!(
+                    "Key maps {token:?} to {letter:?}, but also excludes it"
+                ));
+            }
+            result.insert(c, letter);
+        }
+
+        Ok((result, exclusions))
+    }
+
+    /// Character that a symbol is stored as, or an error if it is not in the ciphertext
+    fn symbol(&self, token: &str) -> Result<char, String> {
+        match self.tokens.iter().position(|t| t == token) {
+            Some(id) => Ok(symbol_char(id)),
+            None => Err(format!("Symbol {token:?} is not in the ciphertext")),
+        }
+    }
+}
+
+fn symbol_char(id: usize) -> char {
+    char::from_u32(FIRST_SYMBOL + id as u32).expect("symbols are in the private use area")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokenize_characters() {
+        let (symbols, ciphertext) = Symbols::tokenize("△○□ ○△", None).unwrap();
+        assert_eq!(symbols.tokens, vec!["△", "○", "□"]);
+        assert_eq!(ciphertext.chars().count(), 6);
+        assert_eq!(symbols.decode(&ciphertext), "△○□ ○△");
+
+        let alphabet = symbols.alphabet(&Alphabet::default());
+        assert_eq!(
+            alphabet.encode_cipher(&ciphertext[..9]),
+            Some(vec![0, 1, 2])
+        );
+    }
+
+    #[test]
+    fn symbol_keys() {
+        let (symbols, _) = Symbols::tokenize("12-5-7 3-1", Some("-")).unwrap();
+        let alphabet = Alphabet::default();
+
+        let (key, _) = symbols.parse_key("12:l,5:e,3:?", &alphabet, 1).unwrap();
+        assert_eq!(key.len(), 2);
+        assert_eq!(symbols.format_key(&key), "12:l,5:e,7:?,3:?,1:?");
+
+        assert_eq!(
+            symbols.parse_key("8:a", &alphabet, 1).unwrap_err(),
+            "Symbol \"8\" is not in the ciphertext"
+        );
+        assert_eq!(
+            symbols.parse_key("12:a,5:a", &alphabet, 1).unwrap_err(),
+            "Duplicate mapping of 'a' to \"12\" and \"5\""
+        );
+        assert!(symbols.parse_key("12:A", &alphabet, 1).is_err());
+        let (key, _) = symbols.parse_key("12:a,5:a", &alphabet, 2).unwrap();
+        assert_eq!(key.len(), 2);
+
+        let (_, exclusions) = symbols.parse_key("12!e,!z", &alphabet, 1).unwrap();
+        assert!(exclusions.excludes(symbols.symbol("12").unwrap(), 'e'));
+        assert_eq!(exclusions.absent, vec!['z']);
+    }
+}
@@ -0,0 +1,80 @@
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, Read},
+    path::Path,
+};
+
+use flate2::bufread::MultiGzDecoder;
+use xz2::bufread::XzDecoder;
+
+/// First bytes of a gzip file
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+/// First bytes of an xz file
+const XZ_MAGIC: &[u8] = &[0xfd, b'7', b'z', b'X', b'Z', 0];
+
+/// Open a wordlist file to read it line by line, decompressing it if it is compressed with gzip or xz
+pub fn open_wordlist(path: &Path) -> io::Result<Box<dyn BufRead>> {
+    decompress(File::open(path)?)
+}
+
+/// Decompress a reader while reading from it if it starts like a gzip or xz file, otherwise read it as it is.
+/// Files with multiple compressed parts after each other are read completely, like `zcat` and `xzcat` do
+pub fn decompress<'a>(reader: impl Read + 'a) -> io::Result<Box<dyn BufRead + 'a>> {
+    let mut reader = BufReader::new(reader);
+    let start = reader.fill_buf()?;
+
+    Ok(if start.starts_with(GZIP_MAGIC) {
+        Box::new(BufReader::new(MultiGzDecoder::new(reader)))
+    } else if start.starts_with(XZ_MAGIC) {
+        Box::new(BufReader::new(XzDecoder::new_multi_decoder(reader)))
+    } else {
+        Box::new(reader)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::{write::GzEncoder, Compression};
+    use xz2::write::XzEncoder;
+
+    use crate::{alphabet::Alphabet, load_wordlist, read_wordlist};
+
+    use super::*;
+
+    #[test]
+    fn decompress_wordlists() {
+        let content = "the\t10\nand\t20\nfew\nwords\na\n";
+        let alphabet = Alphabet::default();
+
+        let mut gzip = Vec::new();
+        // Two parts, like a wordlist that was appended to
+        for part in ["the\t10\nand\t20\n", "few\nwords\na\n"] {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(part.as_bytes()).unwrap();
+            gzip.extend(encoder.finish().unwrap());
+        }
+        let mut encoder = XzEncoder::new(Vec::new(), 6);
+        encoder.write_all(content.as_bytes()).unwrap();
+        let xz = encoder.finish().unwrap();
+
+        for bytes in [content.as_bytes(), &gzip, &xz] {
+            let mut decompressed = String::new();
+            decompress(bytes)
+                .unwrap()
+                .read_to_string(&mut decompressed)
+                .unwrap();
+            assert_eq!(decompressed, content);
+
+            let dictionary =
+                read_wordlist(decompress(bytes).unwrap(), &alphabet, |_| true).unwrap();
+            assert_eq!(dictionary, load_wordlist(content, &alphabet));
+        }
+
+        // Cut off
+        let cut_off = decompress(&xz[..xz.len() - 4]).unwrap();
+        assert!(read_wordlist(cut_off, &alphabet, |_| true).is_err());
+        assert!(decompress(&[][..]).unwrap().lines().next().is_none());
+    }
+}